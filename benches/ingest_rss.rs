@@ -0,0 +1,62 @@
+// Peak RSS while stream-parsing synthetic Uniswap dumps of growing size.
+// The set of pools is fixed and only the filler payload grows, so VmHWM
+// should stay flat if parsing doesn't buffer the input.
+//
+//   cargo bench --bench ingest_rss
+
+use std::{fs, io::{BufReader, BufWriter, Write}, time::Instant};
+use builder::sources::{ParseContext, SourceParser, UniswapParser};
+
+const POOLS: usize = 10_000;
+const SIZES_MB: [usize; 4] = [16, 64, 256, 1024];
+
+fn main() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("ingest_rss-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("pools.json");
+
+    println!("{:>8}  {:>8}  {:>10}  {:>8}", "input", "pools", "peak rss", "secs");
+    for mb in SIZES_MB {
+        write_input(&path, mb << 20)?;
+        let start = Instant::now();
+        let mut input = BufReader::new(fs::File::open(&path)?);
+        let pools = UniswapParser::default().parse(&mut input, &mut ParseContext::new("uniswap", &path))?;
+        println!(
+            "{:>6}MB  {:>8}  {:>8}kB  {:>8.2}",
+            mb, pools.len(), peak_rss_kb()?, start.elapsed().as_secs_f64()
+        );
+    }
+
+    fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+// `{"swaps": [{"pool": "0x..", "tx": "..", "amounts": [..]}, ...]}` with pools
+// cycling through a fixed set, until `size` bytes are written.
+fn write_input(path: &std::path::Path, size: usize) -> anyhow::Result<()> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    let mut written = 0;
+    write!(out, "{{\"swaps\": [")?;
+    for i in 0.. {
+        if written >= size {
+            break;
+        }
+        let row = format!(
+            "{}{{\"pool\": \"0x{:064x}\", \"tx\": \"{:0128x}\", \"amounts\": [{}, -{}.5]}}",
+            if i == 0 { "" } else { "," }, i % POOLS, i, i, i
+        );
+        written += row.len();
+        out.write_all(row.as_bytes())?;
+    }
+    write!(out, "]}}")?;
+    out.flush()?;
+    Ok(())
+}
+
+fn peak_rss_kb() -> anyhow::Result<u64> {
+    let status = fs::read_to_string("/proc/self/status")?;
+    status.lines()
+        .find_map(|l| l.strip_prefix("VmHWM:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("no VmHWM in /proc/self/status"))
+}
@@ -0,0 +1,126 @@
+use std::{env, fmt::Write as _, fs, path::{Path, PathBuf}};
+use fst::{IntoStreamer, Map, Streamer};
+use serde_json::Value;
+
+// With the `embedded` feature, bakes a built snapshot into the binary as a
+// `phf::Map<&[u8], u64>` plus metadata consts (see src/embedded.rs).
+// TOPIC_MAP_EMBED selects the snapshot directory or root, relative to the crate;
+// `none` embeds an empty map.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    if env::var_os("CARGO_FEATURE_EMBEDDED").is_none() {
+        return;
+    }
+    println!("cargo:rerun-if-env-changed=TOPIC_MAP_EMBED");
+
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let chosen = env::var("TOPIC_MAP_EMBED").ok();
+    let root = manifest_dir.join(chosen.as_deref().unwrap_or("snapshots"));
+    let dir = resolve(&root);
+
+    // An empty map only when asked for: a service built from a fresh
+    // checkout must not ship without topics
+    let Embedded { manifest, keys, ids } = if chosen.as_deref() == Some("none") {
+        println!("cargo:warning=embedded topic map is empty (TOPIC_MAP_EMBED=none)");
+        Embedded { manifest: Value::Null, keys: vec![], ids: vec![] }
+    } else {
+        println!("cargo:rerun-if-changed={}", root.join("current").display());
+        println!("cargo:rerun-if-changed={}", dir.join("manifest.json").display());
+        println!("cargo:rerun-if-changed={}", dir.join("topic.map.fst").display());
+        match load(&dir) {
+            Ok(loaded) => loaded,
+            Err(e) => panic!(
+                "embedded topic map: {e}; build a snapshot, point TOPIC_MAP_EMBED at one, \
+                 or set TOPIC_MAP_EMBED=none for an empty map"
+            ),
+        }
+    };
+    let key_refs: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+    let state = phf_generator::generate_hash(&key_refs);
+
+    let mut out = String::new();
+    writeln!(out, "pub static MAP: phf::Map<&'static [u8], u64> = phf::Map {{").unwrap();
+    writeln!(out, "    key: {},", state.key).unwrap();
+    writeln!(out, "    disps: &{:?},", state.disps).unwrap();
+    writeln!(out, "    entries: &[").unwrap();
+    for &i in &state.map {
+        writeln!(out, "        (b\"{}\", {}),", escape(&keys[i]), ids[i]).unwrap();
+    }
+    writeln!(out, "    ],\n}};\n").unwrap();
+
+    let snapshot = dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let map_blake3 = manifest["files"].as_array()
+        .and_then(|files| files.iter().find(|f| f["name"] == "topic.map.fst"))
+        .and_then(|f| f["blake3"].as_str())
+        .unwrap_or_default();
+    writeln!(out, "pub const SNAPSHOT: &str = {snapshot:?};").unwrap();
+    writeln!(out, "pub const BUILT_AT: u64 = {};", manifest["built_at"].as_u64().unwrap_or(0)).unwrap();
+    writeln!(out, "pub const MAP_BLAKE3: &str = {map_blake3:?};").unwrap();
+    writeln!(out, "pub const LEN: usize = {};", keys.len()).unwrap();
+    writeln!(out, "/// (source, id_start, id_end)").unwrap();
+    writeln!(out, "pub static SOURCES: &[(&str, u64, u64)] = &[").unwrap();
+    if let Some(sources) = manifest["sources"].as_object() {
+        for (name, s) in sources {
+            let (start, end) = (s["id_start"].as_u64().unwrap_or(0), s["id_end"].as_u64().unwrap_or(0));
+            writeln!(out, "    ({name:?}, {start}, {end}),").unwrap();
+        }
+    }
+    writeln!(out, "];").unwrap();
+
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap()).join("embedded_topic_map.rs");
+    fs::write(out_path, out).unwrap();
+}
+
+struct Embedded {
+    manifest: Value,
+    keys: Vec<Vec<u8>>,
+    ids: Vec<u64>,
+}
+
+// A v2 snapshot's manifest and entries, with the FST checked against the
+// manifest's blake3 and its own checksum
+fn load(dir: &Path) -> Result<Embedded, String> {
+    let manifest: Value = read(dir, "manifest.json")
+        .and_then(|b| serde_json::from_slice(&b).map_err(|e| e.to_string()))?;
+    if manifest["version"].as_u64() != Some(2) {
+        return Err(format!("{} needs a v2 manifest, rebuild the snapshot", dir.display()));
+    }
+
+    let bytes = read(dir, "topic.map.fst")?;
+    let expected = manifest["files"].as_array()
+        .and_then(|files| files.iter().find(|f| f["name"] == "topic.map.fst"))
+        .and_then(|f| f["blake3"].as_str())
+        .ok_or("manifest lists no blake3 for topic.map.fst")?;
+    let actual = blake3::hash(&bytes).to_hex();
+    if actual.as_str() != expected {
+        return Err(format!("topic.map.fst blake3 is {actual}, manifest says {expected}"));
+    }
+    let map = Map::new(bytes).map_err(|e| format!("invalid FST: {e}"))?;
+    map.as_fst().verify().map_err(|e| format!("FST checksum mismatch: {e}"))?;
+
+    let mut keys: Vec<Vec<u8>> = Vec::with_capacity(map.len());
+    let mut ids: Vec<u64> = Vec::with_capacity(map.len());
+    let mut stream = map.into_stream();
+    while let Some((k, v)) = stream.next() {
+        keys.push(k.to_vec());
+        ids.push(v);
+    }
+    Ok(Embedded { manifest, keys, ids })
+}
+
+// Same rule as `snapshot::resolve`: follow `current` if the root has one
+fn resolve(root: &Path) -> PathBuf {
+    match fs::read_link(root.join("current")) {
+        Ok(target) => root.join(target),
+        Err(_) => root.to_path_buf(),
+    }
+}
+
+fn read(dir: &Path, name: &str) -> Result<Vec<u8>, String> {
+    let path = dir.join(name);
+    fs::read(&path).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn escape(key: &[u8]) -> String {
+    key.iter().map(|b| format!("\\x{b:02x}")).collect()
+}
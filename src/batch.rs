@@ -0,0 +1,165 @@
+use fst::{IntoStreamer, Map, Streamer};
+use crate::TopicMap;
+
+/// Keys resolved per chunk; each chunk is sorted and merge-joined with one
+/// FST stream instead of doing a `get` per key.
+pub const DEFAULT_CHUNK: usize = 64 * 1024;
+
+// When the FST stream is this many entries behind the next wanted key, seek
+// instead of stepping, so sparse chunks don't walk the whole key space.
+const MAX_STEPS: usize = 16;
+
+/// Iterator returned by `TopicMap::lookup_batch`: yields every input key
+/// with its topic ID (or `None`), in input order.
+pub struct BatchLookup<'m, I: Iterator> {
+    map: &'m TopicMap,
+    keys: I,
+    chunk_size: usize,
+    ready: std::vec::IntoIter<(I::Item, Option<u64>)>,
+}
+
+impl TopicMap {
+    pub fn lookup_batch<I>(&self, keys: I) -> BatchLookup<'_, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        BatchLookup {
+            map: self,
+            keys: keys.into_iter(),
+            chunk_size: DEFAULT_CHUNK,
+            ready: Vec::new().into_iter(),
+        }
+    }
+}
+
+impl<I: Iterator> BatchLookup<'_, I> {
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+}
+
+impl<I> Iterator for BatchLookup<'_, I>
+where
+    I: Iterator,
+    I::Item: AsRef<[u8]>,
+{
+    type Item = (I::Item, Option<u64>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(item) = self.ready.next() {
+            return Some(item);
+        }
+        let chunk: Vec<I::Item> = self.keys.by_ref().take(self.chunk_size).collect();
+        if chunk.is_empty() {
+            return None;
+        }
+        self.ready = resolve_chunk(self.map.as_fst(), chunk).into_iter();
+        self.ready.next()
+    }
+}
+
+fn resolve_chunk<K, D>(fst: &Map<D>, chunk: Vec<K>) -> Vec<(K, Option<u64>)>
+where
+    K: AsRef<[u8]>,
+    D: AsRef<[u8]>,
+{
+    let mut order: Vec<usize> = (0..chunk.len()).collect();
+    order.sort_by(|&a, &b| chunk[a].as_ref().cmp(chunk[b].as_ref()));
+    let max = chunk[*order.last().unwrap()].as_ref();
+
+    let mut ids = vec![None; chunk.len()];
+    let mut stream = fst.range().ge(chunk[order[0]].as_ref()).le(max).into_stream();
+    let mut cur = stream.next();
+    for &i in &order {
+        let key = chunk[i].as_ref();
+        let mut steps = 0;
+        while let Some((k, _)) = cur {
+            if k >= key {
+                break;
+            }
+            if steps == MAX_STEPS {
+                stream = fst.range().ge(key).le(max).into_stream();
+                cur = stream.next();
+                break;
+            }
+            steps += 1;
+            cur = stream.next();
+        }
+        match cur {
+            Some((k, id)) if k == key => ids[i] = Some(id),
+            Some(_) => {}
+            None => break,
+        }
+    }
+
+    chunk.into_iter().zip(ids).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    // Even-numbered keys K0000..K1998, so odd ones are misses between hits
+    fn map(name: &str) -> (std::path::PathBuf, TopicMap) {
+        let root = testutil::root(name);
+        let keys: Vec<String> = (0..1000).map(|i| format!("K{:04}", i * 2)).collect();
+        let pairs: Vec<(&str, u64)> = keys.iter().enumerate().map(|(i, k)| (k.as_str(), i as u64)).collect();
+        let map = testutil::snapshot(&root, testutil::manifest(1, &[("binance", 0, 1000)]), &pairs, None);
+        (root, map)
+    }
+
+    // Batch results, in input order, match one `get` per key
+    fn check(map: &TopicMap, keys: &[String], chunk_size: usize) {
+        let got: Vec<(&String, Option<u64>)> = map.lookup_batch(keys).chunk_size(chunk_size).collect();
+        let want: Vec<(&String, Option<u64>)> = keys.iter().map(|k| (k, map.get(k))).collect();
+        assert_eq!(got, want, "chunk size {chunk_size}");
+    }
+
+    fn keys(ns: impl IntoIterator<Item = usize>) -> Vec<String> {
+        ns.into_iter().map(|n| format!("K{n:04}")).collect()
+    }
+
+    #[test]
+    fn matches_get() {
+        let (root, map) = map("batch-get");
+        let cases = [
+            // Dense: every entry, hits and misses, steps without seeking
+            keys(0..200),
+            // Sparse: gaps far beyond MAX_STEPS force seeks
+            keys((0..2000).step_by(97)),
+            // Duplicates, adjacent and apart
+            keys([4, 4, 6, 1500, 4, 1500, 7, 7]),
+            // Unsorted
+            keys([1998, 0, 1001, 500, 2, 1999, 40, 38]),
+            // Past the last key: the stream ends early
+            keys([1996, 1998, 2000, 5000, 9998]),
+            keys([5000, 9000]),
+            vec!["A".to_string(), "K".to_string(), "K0000X".to_string(), "Z".to_string()],
+        ];
+        for case in &cases {
+            for chunk_size in [1, 3, 64, DEFAULT_CHUNK] {
+                check(&map, case, chunk_size);
+            }
+        }
+        assert_eq!(map.lookup_batch(Vec::<String>::new()).count(), 0);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn seeks_after_max_steps() {
+        let (root, map) = map("batch-seek");
+        // Gaps just under, at and over MAX_STEPS entries
+        for gap in [MAX_STEPS - 1, MAX_STEPS, MAX_STEPS + 1, 3 * MAX_STEPS] {
+            let ks = keys((0..2000).step_by(2 * gap));
+            let ids = resolve_chunk(map.as_fst(), ks.clone());
+            for (key, id) in ids {
+                assert_eq!(id, map.get(&key), "{key} with gap {gap}");
+                assert!(id.is_some());
+            }
+        }
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
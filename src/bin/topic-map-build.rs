@@ -1,136 +1,230 @@
-pub mod utils;
-use std::{env, fs, path::Path};
-use fst::MapBuilder;
-use serde_json::Value;
-use std::collections::HashSet;
-use utils::{
-    looks_like_0x32bytes,
-    parse_hex_0x_to_b32
-};
-
-trait SourceParser {
-    fn parse(&self, data: &str) -> anyhow::Result<Vec<Vec<u8>>>;
-}
-
-struct BinanceParser;
-struct UniswapParser;
-
-impl SourceParser for BinanceParser {
-    fn parse(&self, data: &str) -> anyhow::Result<Vec<Vec<u8>>> {
-        let json: Value = serde_json::from_str(data)?;
-        let symbols = json.as_object()
-            .ok_or_else(|| anyhow::anyhow!("Expected JSON object"))?
-            .keys()
-            .map(|k| k.as_bytes().to_vec())
-            .collect();
-        Ok(symbols)
-    }
-}
-
-impl SourceParser for UniswapParser {
-    fn parse(&self, data: &str) -> anyhow::Result<Vec<Vec<u8>>> {
-        let json: Value = serde_json::from_str(data)?;
-        let mut ids = std::collections::HashSet::<[u8; 32]>::new();
-        collect_b32_hex_strings(&json, &mut ids);
-
-        // Sort for stable ID assignment within source (like before)
-        let mut pools: Vec<[u8; 32]> = ids.into_iter().collect();
-        pools.sort_unstable();
-
-        // IMPORTANT: store raw 32 bytes (no "0x", no hex encoding)
-        Ok(pools.into_iter().map(|p| p.to_vec()).collect())
-    }
-}
-
-
-struct Source<'a> {
-    name: &'a str,
-    parser: Box<dyn SourceParser>,
-    path: &'a str,
-    id_start: u64,
-}
+use std::{collections::BTreeMap, env, fs, path::Path};
+use builder::{build, codegen, diff, snapshot, writer, TopicMap};
+use builder::guard::Guardrails;
+use builder::cache::{SourceCache, CACHE_DIR};
+use builder::dedup::{Dedup, DedupPolicy};
+use builder::hexkey::HexKeys;
+use builder::normalize::Normalize;
+use builder::report::{BuildReport, Phase};
+use builder::sort::{ExternalSorter, DEFAULT_MEM_BUDGET};
+use builder::topic_map::MAP_FILE;
+use builder::validity::History;
+use builder::sources::{BinanceParser, Source, UniswapParser};
+
+const MAX_WARNINGS: usize = 20;
 
 fn main() -> anyhow::Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 4 {
-        eprintln!("Usage: topic-map-build <out_dir> <source1.json> <source2.json> ...");
+    let mut dense_ids = false;
+    let mut keep = snapshot::DEFAULT_KEEP;
+    let mut emit_rust: Option<String> = None;
+    let mut allowlist: Option<String> = None;
+    let mut emit_enum = false;
+    let mut use_cache = true;
+    let mut mem_budget = DEFAULT_MEM_BUDGET;
+    let mut threads = 0;
+    let mut strict = false;
+    let mut guardrails = Guardrails::default();
+    let mut force = false;
+    let mut dedup: BTreeMap<String, DedupPolicy> = BTreeMap::new();
+    let mut normalize: BTreeMap<String, Normalize> = BTreeMap::new();
+    let mut hex_keys = HexKeys::default();
+    let mut args: Vec<String> = vec![];
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--dense" => dense_ids = true,
+            "--keep" => {
+                keep = argv.next()
+                    .ok_or_else(|| anyhow::anyhow!("--keep needs a value"))?
+                    .parse()?;
+            }
+            "--emit-rust" => {
+                emit_rust = Some(argv.next().ok_or_else(|| anyhow::anyhow!("--emit-rust needs a file"))?);
+            }
+            "--allowlist" => {
+                allowlist = Some(argv.next().ok_or_else(|| anyhow::anyhow!("--allowlist needs a file"))?);
+            }
+            "--emit-enum" => emit_enum = true,
+            "--no-cache" => use_cache = false,
+            "--force" => force = true,
+            "--max-removed-pct" => {
+                guardrails.max_removed_pct = Some(argv.next()
+                    .ok_or_else(|| anyhow::anyhow!("--max-removed-pct needs a value"))?
+                    .parse()?);
+            }
+            "--max-churn" => {
+                guardrails.max_churn = Some(argv.next()
+                    .ok_or_else(|| anyhow::anyhow!("--max-churn needs a value"))?
+                    .parse()?);
+            }
+            "--min-count" => {
+                guardrails.min_count = Some(argv.next()
+                    .ok_or_else(|| anyhow::anyhow!("--min-count needs a value"))?
+                    .parse()?);
+            }
+            "--dedup" => {
+                let value = argv.next().ok_or_else(|| anyhow::anyhow!("--dedup needs <source>=<policy>"))?;
+                let (source, policy) = value.split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("--dedup needs <source>=<policy>, got {value:?}"))?;
+                dedup.insert(source.to_string(), policy.parse()?);
+            }
+            "--normalize" => {
+                let value = argv.next().ok_or_else(|| anyhow::anyhow!("--normalize needs <source>=<rules>"))?;
+                let (source, rules) = value.split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("--normalize needs <source>=<rules>, got {value:?}"))?;
+                normalize.insert(source.to_string(), rules.parse()?);
+            }
+            "--hex-keys" => {
+                hex_keys = argv.next()
+                    .ok_or_else(|| anyhow::anyhow!("--hex-keys needs a value"))?
+                    .parse()?;
+            }
+            "--strict" => strict = true,
+            "--lenient" => strict = false,
+            "--mem-budget" => {
+                let mb: usize = argv.next()
+                    .ok_or_else(|| anyhow::anyhow!("--mem-budget needs a value in MB"))?
+                    .parse()?;
+                mem_budget = mb << 20;
+            }
+            "--threads" => {
+                threads = argv.next()
+                    .ok_or_else(|| anyhow::anyhow!("--threads needs a value"))?
+                    .parse()?;
+            }
+            s if s.starts_with("--") => anyhow::bail!("unknown option {s}"),
+            _ => args.push(arg),
+        }
+    }
+    if args.len() < 3 {
+        eprintln!("Usage: topic-map-build [--dense] [--keep N] [--no-cache] [--strict | --lenient] [--dedup <source>=error|keep-first|alias] [--normalize <source>=upper|lower,strip=<chars>,variants] [--hex-keys upper-prefix,unprefixed,addresses,no-checksum] [--threads N] [--mem-budget MB] [--max-removed-pct P] [--max-churn N] [--min-count N] [--force] [--emit-rust <file> --allowlist <file> [--emit-enum]] <out_dir> <source1.json> <source2.json> ...");
         std::process::exit(1);
     }
+    if emit_rust.is_some() != allowlist.is_some() {
+        anyhow::bail!("--emit-rust and --allowlist go together");
+    }
+    // Checked up front, so a bad allowlist fails before anything is built
+    let allowlist = match &allowlist {
+        Some(path) => {
+            let text = fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{path}: {e}"))?;
+            let entries = codegen::parse_allowlist(&text)?;
+            codegen::check_names(&entries, emit_enum)?;
+            Some(entries)
+        }
+        None => None,
+    };
 
-    let out_dir = Path::new(&args[1]);
-    fs::create_dir_all(out_dir)?;
+    // Each build gets a fresh numbered directory under out_dir; `current` is
+    // switched only once everything is written and synced.
+    let root = Path::new(&args[0]);
 
     // Define sources with ID ranges
     let sources = vec![
         Source {
             name: "binance",
             parser: Box::new(BinanceParser),
-            path: &args[2],
+            path: &args[1],
             id_start: 0,
+            dedup: dedup.get("binance").copied().unwrap_or_default(),
+            normalize: normalize.get("binance").cloned(),
         },
         Source {
             name: "uniswap",
-            parser: Box::new(UniswapParser),
-            path: &args[3],
+            parser: Box::new(UniswapParser { hex: hex_keys }),
+            path: &args[2],
             id_start: 10_000,
+            dedup: dedup.get("uniswap").copied().unwrap_or_default(),
+            normalize: normalize.get("uniswap").cloned(),
         },
     ];
 
-    let mut all_pairs = vec![];
-    let mut manifest = serde_json::Map::new();
-
-    for source in sources {
-        let data = fs::read_to_string(source.path)?;
-        let entries = source.parser.parse(&data)?;
-        let pairs: Vec<_> = entries
-            .into_iter()
-            .enumerate()
-            .map(|(i, k)| (k, source.id_start + i as u64))
-            .collect();
-        manifest.insert(
-            source.name.to_string(),
-            serde_json::json!({ "count": pairs.len() }),
-        );
-        all_pairs.extend(pairs);
+    // Parsed sources are cached under <out_dir>/.cache/<source> by input hash, so only
+    // changed inputs are re-parsed
+    let cache = use_cache.then(|| SourceCache::new(&root.join(CACHE_DIR)));
+    // Sources are parsed in parallel (0 threads = one per core); sorted runs
+    // past the memory budget spill under <out_dir>/.spill-<pid> until merged
+    rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+    let sorter = ExternalSorter::new(&root.join(format!(".spill-{}", std::process::id())), mem_budget);
+    if let Some(unknown) = dedup.keys().find(|name| !sources.iter().any(|s| s.name == name.as_str())) {
+        anyhow::bail!("--dedup: unknown source {unknown:?}");
     }
-
-    all_pairs.sort_by(|a, b| a.0.cmp(&b.0));
-
-    let swap_path = out_dir.join("topic.map.fst");
-    let file = std::fs::File::create(&swap_path)?;
-    let mut builder = MapBuilder::new(file)?;
-    for (k, v) in &all_pairs {
-        builder.insert(k, *v)?;
+    if let Some(unknown) = normalize.keys().find(|name| !sources.iter().any(|s| s.name == name.as_str())) {
+        anyhow::bail!("--normalize: unknown source {unknown:?}");
+    }
+    let mut phases = vec![];
+    // --strict fails on malformed records; the default (--lenient) skips them
+    let build::Built { pairs, mut manifest, spilled, rejected, mut reports, variants, listed } =
+        Phase::time(&mut phases, "parse", || build::build(sources, cache.as_ref(), sorter, strict))?;
+    for (name, report) in reports.iter().filter(|(_, r)| r.cached) {
+        eprintln!("{name}: {} keys from cache", report.parsed);
+    }
+    for e in rejected.iter().take(MAX_WARNINGS) {
+        eprintln!("warning: skipped {e}");
+    }
+    if rejected.len() > MAX_WARNINGS {
+        eprintln!("warning: ... and {} more skipped records", rejected.len() - MAX_WARNINGS);
+    }
+    if spilled > 0 {
+        eprintln!("Spilled {spilled} sorted runs to disk");
     }
-    builder.finish()?;
-
-    manifest.insert("version".to_string(), serde_json::json!(1));
-    fs::write(out_dir.join("manifest.json"), serde_json::to_string_pretty(&manifest)?)?;
-
-    eprintln!("Wrote {} ({} entries)", swap_path.display(), all_pairs.len());
-    Ok(())
-}
 
-fn collect_b32_hex_strings(v: &Value, out: &mut HashSet<[u8; 32]>) {
-    match v {
-        Value::String(s) => {
-            if looks_like_0x32bytes(s) {
-                if let Ok(b) = parse_hex_0x_to_b32(s) {
-                    out.insert(b);
-                }
-            }
+    // Keys in several sources go to the one with the lowest ID; the others'
+    // --dedup policy decides between failing, dropping and aliasing
+    let mut deduped = Dedup::new(pairs, &manifest, &dedup, &mut reports);
+    // Key validity intervals carry over from the snapshot being replaced
+    let previous = match snapshot::current(root)? {
+        Some(_) => Some(TopicMap::open(root)?),
+        None => None,
+    };
+    let history = History::new(previous.as_ref(), listed);
+    let mut written = writer::stage(root, &mut manifest, &mut deduped, variants, Some(history), dense_ids)?;
+    phases.append(&mut written.phases);
+    // Staged snapshots only become `current` once they pass the guardrails
+    // against the snapshot they replace
+    let violations = Phase::time(&mut phases, "guard", || -> anyhow::Result<_> {
+        let staged = TopicMap::open(&written.dir)?;
+        let d = previous.as_ref().map(|old| diff::diff(old, &staged));
+        Ok(guardrails.check(previous.as_ref().map(TopicMap::manifest).zip(d.as_ref()), &manifest))
+    })?;
+    if !violations.is_empty() {
+        let details: Vec<String> = violations.iter().map(|v| v.to_string()).collect();
+        if !force {
+            written.discard()?;
+            anyhow::bail!(
+                "snapshot rejected by guardrails (--force to publish anyway):\n  {}",
+                details.join("\n  ")
+            );
         }
-        Value::Array(arr) => {
-            for item in arr {
-                collect_b32_hex_strings(item, out);
-            }
+        for d in &details {
+            eprintln!("warning: guardrail overridden by --force: {d}");
         }
-        Value::Object(map) => {
-            for (key, val) in map {
-                collect_b32_hex_strings(&Value::String(key.clone()), out); // scan key
-                collect_b32_hex_strings(val, out);                         // scan value
+    }
+    // Resolved against the staged snapshot: an allowlist naming an unknown
+    // source must not leave a new `current` behind
+    let resolved = match &allowlist {
+        Some(entries) => match TopicMap::open(&written.dir).and_then(|map| codegen::resolve(entries, &map)) {
+            Ok(resolved) => Some(resolved),
+            Err(e) => {
+                written.discard()?;
+                return Err(e);
             }
+        },
+        None => None,
+    };
+    written.publish(root)?;
+    eprintln!("Wrote {} ({} entries)", written.dir.join(MAP_FILE).display(), written.len);
+    phases.append(&mut written.phases);
+    BuildReport::new(&written.name, written.len, &manifest, reports, phases).write(&written.dir)?;
+
+    if let (Some(rust_path), Some(resolved)) = (&emit_rust, &resolved) {
+        for m in &resolved.missing {
+            eprintln!("warning: allowlisted topic {m} is not in the snapshot, its constant is dropped");
         }
-        _ => {}
+        fs::write(rust_path, codegen::emit_rust(&resolved.topics, &written.name, emit_enum))?;
+        eprintln!("Wrote {rust_path} ({} topics)", resolved.topics.len());
     }
-}
\ No newline at end of file
+    for name in snapshot::prune(root, keep)? {
+        eprintln!("Pruned snapshot {name}");
+    }
+    Ok(())
+}
@@ -0,0 +1,464 @@
+use std::{env, fs, io::{self, BufRead, BufWriter, Write}, path::PathBuf, process::ExitCode};
+use serde_json::{json, Value};
+use builder::{diff, merge, snapshot, utils, verify, writer, TopicMap};
+use builder::hexkey::{HexKeys, Recognized};
+use builder::query::{Hit, Query};
+
+const USAGE: &str = "\
+Usage: topic-map [--snapshot <dir>] [--json] <command>
+
+Commands:
+  get <key>...            exact lookup; 0x-prefixed 32-byte hex or a symbol
+                          [--normalize: also match other spellings, e.g.
+                          eth-usdt, per the sources' normalization rules]
+                          [--as-of <unix seconds>: only keys listed then]
+  id <n>...               reverse lookup, topic ID -> key
+  lookup --batch [<file>...]
+                          resolve keys from files or stdin (one per line, or
+                          a CSV column with --csv <name|index>); writes key,id
+                          to stdout or --out <file>, misses to --misses <file>
+  search <query>          ETH* (prefix) | A..B (range) | /regex/ | key
+                          [--source NAME] [--limit N]
+  dump                    every key and ID, in key order [--source NAME]
+  diff <old> <new>        added/removed keys, keys whose ID changed and manifest
+                          changes between two snapshots [--limit N per list]
+  merge --out <root> <snapshot>...
+                          union snapshots into a new snapshot under <root>
+                          [--on-conflict error|prefer-left|prefer-newest]
+                          [--dense] [--keep N]
+  stats                   entry counts, ID ranges and files of the snapshot
+  verify                  check files against manifest digests and FST checksums
+  rollback [<snapshot>]   point `current` at an older snapshot
+
+--snapshot defaults to snapshots/ (a root with a `current` pointer or a
+snapshot directory). Exit status: 0 ok, 1 some key/ID not found (or, for
+diff, some key changed ID), 2 error.";
+
+struct Opts {
+    snapshot: PathBuf,
+    json: bool,
+    source: Option<String>,
+    limit: Option<usize>,
+    batch: bool,
+    csv: Option<String>,
+    out: Option<PathBuf>,
+    misses: Option<PathBuf>,
+    on_conflict: merge::ConflictPolicy,
+    dense: bool,
+    keep: usize,
+    normalize: bool,
+    as_of: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn run() -> anyhow::Result<ExitCode> {
+    let mut opts = Opts {
+        snapshot: PathBuf::from("snapshots"),
+        json: false,
+        source: None,
+        limit: None,
+        batch: false,
+        csv: None,
+        out: None,
+        misses: None,
+        on_conflict: merge::ConflictPolicy::Error,
+        dense: false,
+        keep: snapshot::DEFAULT_KEEP,
+        normalize: false,
+        as_of: None,
+    };
+    let mut args: Vec<String> = vec![];
+    let mut argv = env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        let mut value = |name: &str| argv.next().ok_or_else(|| anyhow::anyhow!("{name} needs a value"));
+        match arg.as_str() {
+            "--snapshot" => opts.snapshot = PathBuf::from(value("--snapshot")?),
+            "--json" => opts.json = true,
+            "--source" => opts.source = Some(value("--source")?),
+            "--limit" => opts.limit = Some(value("--limit")?.parse()?),
+            "--batch" => opts.batch = true,
+            "--csv" => opts.csv = Some(value("--csv")?),
+            "--out" => opts.out = Some(PathBuf::from(value("--out")?)),
+            "--misses" => opts.misses = Some(PathBuf::from(value("--misses")?)),
+            "--on-conflict" => opts.on_conflict = value("--on-conflict")?.parse()?,
+            "--dense" => opts.dense = true,
+            "--keep" => opts.keep = value("--keep")?.parse()?,
+            "--normalize" => opts.normalize = true,
+            "--as-of" => opts.as_of = Some(value("--as-of")?.parse()?),
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            s if s.starts_with("--") => anyhow::bail!("unknown option {s}"),
+            _ => args.push(arg),
+        }
+    }
+
+    let rest = args.get(1..).unwrap_or_default();
+    match args.first().map(String::as_str) {
+        Some("get") if !rest.is_empty() => cmd_get(&opts, rest),
+        Some("id") if !rest.is_empty() => cmd_id(&opts, rest),
+        Some("lookup") if opts.batch => cmd_lookup_batch(&opts, rest),
+        Some("lookup") if !rest.is_empty() => cmd_get(&opts, rest),
+        Some("search") if rest.len() == 1 => cmd_search(&opts, &rest[0]),
+        Some("dump") if rest.is_empty() => cmd_search(&opts, "..").map(|_| ExitCode::SUCCESS),
+        Some("diff") if rest.len() == 2 => cmd_diff(&opts, &rest[0], &rest[1]),
+        Some("merge") if !rest.is_empty() => cmd_merge(&opts, rest),
+        Some("stats") if rest.is_empty() => cmd_stats(&opts),
+        Some("verify") if rest.is_empty() => cmd_verify(&opts),
+        Some("rollback") if rest.len() <= 1 => cmd_rollback(&opts, rest.first().map(String::as_str)),
+        _ => {
+            eprintln!("{USAGE}");
+            Ok(ExitCode::from(2))
+        }
+    }
+}
+
+fn cmd_get(opts: &Opts, keys: &[String]) -> anyhow::Result<ExitCode> {
+    let map = TopicMap::open(&opts.snapshot)?;
+    let mut missing = false;
+    for key in keys {
+        let raw = utils::parse_key(key)?;
+        let id = match (opts.as_of, opts.normalize) {
+            (Some(t), _) => map.get_as_of(&raw, t),
+            (None, true) => map.normalize_and_get(&raw),
+            (None, false) => map.get(&raw),
+        };
+        let id = id.filter(|&id| in_source(&map, opts, id));
+        match id {
+            // A normalized or variant match shows the key, not the spelling
+            Some(id) if opts.normalize => {
+                let stored = map.key_of(id).unwrap_or(raw);
+                emit(opts, &hit(&map, stored, id))
+            }
+            Some(id) => emit(opts, &hit(&map, raw, id)),
+            None => {
+                missing = true;
+                if opts.json {
+                    println!("{}", json!({ "key": key, "id": null }));
+                }
+                match opts.as_of {
+                    Some(t) if map.get(&raw).is_some() => eprintln!("'{key}' was not listed at {t}."),
+                    _ => suggest(&map, key, opts)?,
+                }
+            }
+        }
+    }
+    Ok(exit_code(missing))
+}
+
+fn cmd_id(opts: &Opts, ids: &[String]) -> anyhow::Result<ExitCode> {
+    let map = TopicMap::open(&opts.snapshot)?;
+    let mut missing = false;
+    for id in ids {
+        let id: u64 = id.parse().map_err(|e| anyhow::anyhow!("topic ID {id:?}: {e}"))?;
+        match map.key_of(id) {
+            Some(key) => emit(opts, &hit(&map, key, id)),
+            None => {
+                missing = true;
+                if opts.json {
+                    println!("{}", json!({ "key": null, "id": id }));
+                } else {
+                    eprintln!("Topic ID {id} not found.");
+                }
+            }
+        }
+    }
+    Ok(exit_code(missing))
+}
+
+// Input text is echoed back in the output; `raw` is what gets looked up.
+struct BatchKey {
+    text: String,
+    raw: Vec<u8>,
+}
+
+impl AsRef<[u8]> for BatchKey {
+    fn as_ref(&self) -> &[u8] {
+        &self.raw
+    }
+}
+
+fn cmd_lookup_batch(opts: &Opts, inputs: &[String]) -> anyhow::Result<ExitCode> {
+    let map = TopicMap::open(&opts.snapshot)?;
+    let inputs: Vec<String> = if inputs.is_empty() { vec!["-".into()] } else { inputs.to_vec() };
+
+    let mut keys: Vec<Box<dyn Iterator<Item = io::Result<String>>>> = vec![];
+    for input in &inputs {
+        let reader: Box<dyn io::Read> = if input == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            Box::new(fs::File::open(input).map_err(|e| anyhow::anyhow!("{input}: {e}"))?)
+        };
+        keys.push(match &opts.csv {
+            Some(column) => csv_column(reader, column)?,
+            None => Box::new(io::BufReader::new(reader).lines()),
+        });
+    }
+
+    // Read errors end the stream; they are reported once it is drained
+    let mut read_error = None;
+    let keys = keys.into_iter().flatten().map_while(|line| match line {
+        Ok(text) => Some(text),
+        Err(e) => {
+            read_error = Some(e);
+            None
+        }
+    });
+    let keys = keys
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+        .map(|text| {
+            let raw = utils::parse_key(&text).unwrap_or_else(|_| text.as_bytes().to_vec());
+            BatchKey { text, raw }
+        });
+
+    let mut out: Box<dyn Write> = match &opts.out {
+        Some(path) => Box::new(BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(BufWriter::new(io::stdout().lock())),
+    };
+    let mut misses: Box<dyn Write> = match &opts.misses {
+        Some(path) => Box::new(BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(io::stderr().lock()),
+    };
+
+    let (mut hits, mut missed) = (0u64, 0u64);
+    for (key, id) in map.lookup_batch(keys) {
+        match id {
+            Some(id) => {
+                hits += 1;
+                writeln!(out, "{},{id}", key.text)?;
+            }
+            None => {
+                missed += 1;
+                writeln!(misses, "{}", key.text)?;
+            }
+        }
+    }
+    out.flush()?;
+    misses.flush()?;
+    if let Some(e) = read_error {
+        anyhow::bail!("reading keys: {e}");
+    }
+
+    eprintln!("{} keys: {hits} found, {missed} missing", hits + missed);
+    Ok(exit_code(missed > 0))
+}
+
+fn csv_column(
+    reader: Box<dyn io::Read>,
+    column: &str,
+) -> anyhow::Result<Box<dyn Iterator<Item = io::Result<String>>>> {
+    let mut csv = csv::Reader::from_reader(reader);
+    let headers = csv.headers()?;
+    let index = match column.parse::<usize>() {
+        Ok(i) => i,
+        Err(_) => headers.iter().position(|h| h == column)
+            .ok_or_else(|| anyhow::anyhow!("CSV has no column {column:?}"))?,
+    };
+    Ok(Box::new(csv.into_records().map(move |record| {
+        let record = record.map_err(io::Error::other)?;
+        Ok(record.get(index).unwrap_or_default().to_string())
+    })))
+}
+
+fn cmd_search(opts: &Opts, query: &str) -> anyhow::Result<ExitCode> {
+    let map = TopicMap::open(&opts.snapshot)?;
+    let query = Query::parse(query);
+    let hits = map.search(&query, opts.source.as_deref(), opts.limit)?;
+    for hit in &hits {
+        emit(opts, hit);
+    }
+    if let (Query::Exact(key), true) = (&query, hits.is_empty()) {
+        suggest(&map, &String::from_utf8_lossy(key), opts)?;
+    }
+    Ok(exit_code(hits.is_empty()))
+}
+
+fn cmd_diff(opts: &Opts, old: &str, new: &str) -> anyhow::Result<ExitCode> {
+    let old = TopicMap::open(old.as_ref())?;
+    let new = TopicMap::open(new.as_ref())?;
+    let d = diff::diff(&old, &new);
+    let limit = opts.limit.unwrap_or(usize::MAX);
+
+    if opts.json {
+        let per_source: serde_json::Map<String, Value> = d.per_source.iter()
+            .map(|(name, s)| (name.clone(), json!({ "added": s.added, "removed": s.removed, "changed": s.changed })))
+            .collect();
+        let report = json!({
+            "old": old.dir().display().to_string(),
+            "new": new.dir().display().to_string(),
+            "summary": {
+                "added": d.added.len(),
+                "removed": d.removed.len(),
+                "changed": d.changed.len(),
+                "per_source": per_source,
+            },
+            "added": d.added.iter().take(limit)
+                .map(|(k, id)| json!({ "key": utils::display_key(k), "id": id })).collect::<Vec<_>>(),
+            "removed": d.removed.iter().take(limit)
+                .map(|(k, id)| json!({ "key": utils::display_key(k), "id": id })).collect::<Vec<_>>(),
+            "changed": d.changed.iter().take(limit)
+                .map(|(k, o, n)| json!({ "key": utils::display_key(k), "old_id": o, "new_id": n })).collect::<Vec<_>>(),
+            "metadata": d.metadata.iter()
+                .map(|m| json!({ "field": m.field, "old": m.old, "new": m.new })).collect::<Vec<_>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("{:<10} {:>8} {:>8} {:>8}", "source", "added", "removed", "changed");
+        for (name, s) in &d.per_source {
+            println!("{name:<10} {:>8} {:>8} {:>8}", s.added, s.removed, s.changed);
+        }
+        for (k, id) in d.added.iter().take(limit) {
+            println!("+ {}\t{id}", utils::display_key(k));
+        }
+        for (k, id) in d.removed.iter().take(limit) {
+            println!("- {}\t{id}", utils::display_key(k));
+        }
+        for (k, o, n) in d.changed.iter().take(limit) {
+            println!("! {}\t{o} -> {n}", utils::display_key(k));
+        }
+        for m in &d.metadata {
+            println!("~ {}: {} -> {}", m.field, m.old, m.new);
+        }
+    }
+    Ok(exit_code(!d.changed.is_empty()))
+}
+
+fn cmd_merge(opts: &Opts, inputs: &[String]) -> anyhow::Result<ExitCode> {
+    let root = opts.out.as_deref().ok_or_else(|| anyhow::anyhow!("merge needs --out <root>"))?;
+    let maps = inputs.iter()
+        .map(|dir| TopicMap::open_verified(dir.as_ref()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let mut merged = merge::merge(&maps, opts.on_conflict)?;
+    for (key, ids) in &merged.conflicts {
+        eprintln!("conflict: {} {ids:?}", utils::display_key(key));
+    }
+    let conflicts = merged.conflicts.len();
+    let written = writer::write_snapshot(root, &mut merged.manifest, merged.pairs.into_iter().map(Ok), merged.variants, merged.history, opts.dense)?;
+    if opts.json {
+        println!("{}", json!({
+            "snapshot": written.dir.display().to_string(),
+            "entries": written.len,
+            "conflicts": conflicts,
+        }));
+    } else {
+        println!("Wrote {} ({} entries, {} conflicts resolved)", written.dir.display(), written.len, conflicts);
+    }
+    for name in snapshot::prune(root, opts.keep)? {
+        eprintln!("Pruned snapshot {name}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_stats(opts: &Opts) -> anyhow::Result<ExitCode> {
+    let map = TopicMap::open(&opts.snapshot)?;
+    let manifest = map.manifest();
+    if opts.json {
+        let mut stats = serde_json::to_value(manifest)?;
+        stats["snapshot"] = json!(map.dir().display().to_string());
+        stats["entries"] = json!(map.len());
+        stats["mmap"] = json!(map.is_mmap());
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("snapshot  {}", map.dir().display());
+    println!("version   {} (builder {})", manifest.version, manifest.builder_version);
+    if let Some(built_at) = manifest.built_at {
+        println!("built_at  {built_at}");
+    }
+    println!("entries   {}{}", map.len(), if map.is_mmap() { " (mmap)" } else { "" });
+    for (name, s) in &manifest.sources {
+        let normalize = s.normalize.as_ref().map(|n| format!("  normalize {n}")).unwrap_or_default();
+        println!("source    {name:<10} {:>10} IDs {}..{}{normalize}", s.count, s.id_start, s.id_end);
+    }
+    if let Some(dense) = &manifest.dense {
+        println!("dense     {} IDs 0..{}", dense.count, dense.count);
+    }
+    if let Some(v) = &manifest.validity {
+        println!("validity  {} keys, {} listing intervals", v.keys, v.intervals);
+    }
+    for f in &manifest.files {
+        println!("file      {:<18} {:>10} bytes  blake3 {}", f.name, f.size, f.blake3);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_verify(opts: &Opts) -> anyhow::Result<ExitCode> {
+    let dir = snapshot::resolve(&opts.snapshot)?;
+    let manifest = verify::verify_snapshot(&dir)?;
+    if opts.json {
+        println!("{}", json!({ "snapshot": dir.display().to_string(), "ok": true, "files": manifest.files.len() }));
+    } else {
+        println!("OK {} ({} files verified)", dir.display(), manifest.files.len());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cmd_rollback(opts: &Opts, target: Option<&str>) -> anyhow::Result<ExitCode> {
+    let root = opts.snapshot.as_path();
+    let target = match target {
+        // Only snapshots under this root, so `current` stays a sibling name
+        Some(name) if snapshot::list(root)?.iter().any(|s| s == name) => name.to_string(),
+        Some(name) => anyhow::bail!("{}: no snapshot named {name:?}", root.display()),
+        None => snapshot::previous(root)?
+            .ok_or_else(|| anyhow::anyhow!("{}: no older snapshot to roll back to", root.display()))?,
+    };
+    // Never point `current` at something readers would refuse
+    verify::verify_snapshot(&root.join(&target))?;
+    snapshot::publish(root, &target)?;
+    println!("{} -> {target}", root.join(snapshot::CURRENT).display());
+    Ok(ExitCode::SUCCESS)
+}
+
+fn hit(map: &TopicMap, key: Vec<u8>, id: u64) -> Hit {
+    Hit { key, id, source: map.manifest().source_of(id).map(str::to_string) }
+}
+
+fn in_source(map: &TopicMap, opts: &Opts, id: u64) -> bool {
+    match &opts.source {
+        Some(name) => map.manifest().source_of(id) == Some(name.as_str()),
+        None => true,
+    }
+}
+
+fn emit(opts: &Opts, hit: &Hit) {
+    let key = utils::display_key(&hit.key);
+    if opts.json {
+        let source = hit.source.as_deref().map_or(Value::Null, |s| json!(s));
+        println!("{}", json!({ "key": key, "id": hit.id, "source": source }));
+    } else {
+        println!("{key}\t{}\t{}", hit.id, hit.source.as_deref().unwrap_or("-"));
+    }
+}
+
+fn suggest(map: &TopicMap, key: &str, opts: &Opts) -> anyhow::Result<()> {
+    // Edit distance is meaningless for hex IDs, in any spelling
+    if HexKeys::all().recognize(key) != Recognized::None {
+        eprintln!("'{key}' not found.");
+        return Ok(());
+    }
+    let suggestions = map.fuzzy(key, 2, opts.source.as_deref(), Some(5))?;
+    if suggestions.is_empty() {
+        eprintln!("'{key}' not found.");
+    } else {
+        let names: Vec<String> = suggestions.iter().map(|(_, h)| utils::display_key(&h.key)).collect();
+        eprintln!("'{key}' not found. Did you mean: {}?", names.join(", "));
+    }
+    Ok(())
+}
+
+fn exit_code(missing: bool) -> ExitCode {
+    if missing { ExitCode::from(1) } else { ExitCode::SUCCESS }
+}
@@ -0,0 +1,294 @@
+use std::{collections::{BTreeMap, HashMap, HashSet}, path::Path, sync::Mutex, time::SystemTime};
+use rayon::prelude::*;
+use crate::cache::SourceCache;
+use crate::dedup::DedupPolicy;
+use crate::error::{BuildError, BuildFailed};
+use crate::input;
+use crate::manifest::{Manifest, SourceEntry};
+use crate::normalize::{Normalize, Variant};
+use crate::report::SourceReport;
+use crate::sort::{self, ExternalSorter, SortedPairs};
+use crate::sources::{ParseContext, Source};
+use crate::{utils, Pair};
+
+pub struct Built {
+    /// Key-ordered stream, ready for `writer::write_snapshot`.
+    pub pairs: SortedPairs,
+    pub manifest: Manifest,
+    /// Runs the sorter spilled to disk to stay within its memory budget.
+    pub spilled: usize,
+    /// Malformed records skipped by a lenient build.
+    pub rejected: Vec<BuildError>,
+    /// Per-source counts for the build report; `accepted` is filled in from
+    /// the manifest once written.
+    pub reports: BTreeMap<String, SourceReport>,
+    /// Spellings of keys from sources normalized with variants, for
+    /// `writer::stage`.
+    pub variants: Vec<Variant>,
+    /// Listing times from venue fields, for `validity::History`.
+    pub listed: HashMap<Vec<u8>, u64>,
+}
+
+// What the parse workers hand back, behind one lock
+struct Collected {
+    sorter: ExternalSorter,
+    manifest: Manifest,
+    errors: Vec<BuildError>,
+    omitted: u64,
+    reports: BTreeMap<String, SourceReport>,
+    variants: Vec<Variant>,
+    listed: HashMap<Vec<u8>, u64>,
+}
+
+// One source's share of the build
+struct SourceBuilt {
+    entry: SourceEntry,
+    variants: Vec<Variant>,
+    listed: Vec<(Vec<u8>, u64)>,
+}
+
+/// Parses every source (or reuses its cached key list) on the rayon pool,
+/// applies its normalization rules, assigns IDs from its `id_start`, and hands each source to
+/// `sorter` as key-sorted runs of at most `ExternalSorter::run_limit` bytes
+/// per worker, spilled as they pile up and k-way merged on read. A source's
+/// parsed key list is still held in memory until its runs are cut.
+///
+/// Input errors from all sources are collected before failing with
+/// `BuildFailed`. Malformed records fail a `strict` build; otherwise they
+/// are skipped and returned in `Built::rejected`.
+pub fn build(
+    sources: Vec<Source>,
+    cache: Option<&SourceCache>,
+    sorter: ExternalSorter,
+    strict: bool,
+) -> anyhow::Result<Built> {
+    if let Some(s) = sources.iter().find(|s| s.normalize.is_some() && !s.parser.symbol_keys()) {
+        anyhow::bail!("{}: normalization rules need symbol keys, {} keys are raw bytes", s.name, s.parser.name());
+    }
+    let built_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    let state = Mutex::new(Collected {
+        sorter,
+        manifest: Manifest::new(built_at),
+        errors: vec![],
+        omitted: 0,
+        reports: BTreeMap::new(),
+        variants: vec![],
+        listed: HashMap::new(),
+    });
+
+    let run_limit = state.lock().unwrap().sorter.run_limit(rayon::current_num_threads());
+    let add_run = |run: Vec<Pair>| state.lock().unwrap().sorter.add_run(run);
+    sources.into_par_iter().try_for_each(|source| -> anyhow::Result<()> {
+        let (mut errors, mut omitted) = (vec![], 0);
+        let mut report = SourceReport::default();
+        let built = build_source(&source, cache, run_limit, &add_run, &mut errors, &mut omitted, &mut report)?;
+        let mut state = state.lock().unwrap();
+        if let Some(mut built) = built {
+            state.variants.append(&mut built.variants);
+            state.listed.extend(built.listed);
+            state.manifest.sources.insert(source.name.to_string(), built.entry);
+        }
+        state.errors.extend(errors);
+        state.omitted += omitted;
+        state.reports.insert(source.name.to_string(), report);
+        Ok(())
+    })?;
+
+    let Collected { sorter, manifest, mut errors, omitted, reports, variants, listed } = state.into_inner().unwrap();
+    // Sources finish in any order; report them in a stable one
+    errors.sort_by(|a: &BuildError, b| a.source_name().cmp(b.source_name()));
+    if errors.iter().any(BuildError::is_fatal) || (strict && !errors.is_empty()) {
+        return Err(BuildFailed { errors, omitted }.into());
+    }
+    let spilled = sorter.spilled();
+    Ok(Built { pairs: sorter.merge()?, manifest, spilled, rejected: errors, reports, variants, listed })
+}
+
+// `None` when the source had a fatal error, which is pushed to `errors`
+// along with any rejected records. Otherwise its pairs have gone to `add_run`
+// in sorted runs of about `run_limit` bytes. Duplicate errors past a
+// file's sample are counted in `omitted`.
+fn build_source(
+    source: &Source,
+    cache: Option<&SourceCache>,
+    run_limit: usize,
+    add_run: &(dyn Fn(Vec<Pair>) -> anyhow::Result<()> + Sync),
+    errors: &mut Vec<BuildError>,
+    omitted: &mut u64,
+    report: &mut SourceReport,
+) -> anyhow::Result<Option<SourceBuilt>> {
+    let parser = source.parser.name();
+    let io_error = |e: anyhow::Error| BuildError::io(source.name.into(), Path::new(source.path).into(), e);
+
+    // Inputs can be several GB: hash in one streaming pass, and only
+    // stream-parse on a cache miss
+    let hashed = input::expand(source.path).and_then(|files| Ok((input::blake3(&files)?, files)));
+    let (input_blake3, files) = match hashed {
+        Ok(hashed) => hashed,
+        Err(e) => {
+            errors.push(io_error(e));
+            return Ok(None);
+        }
+    };
+    report.files = files.len();
+    let mut listed = vec![];
+    let cache_key = match source.parser.cache_tag() {
+        tag if tag.is_empty() => format!("v{}-{input_blake3}", source.parser.version()),
+        tag => format!("v{}-{input_blake3}-{tag}", source.parser.version()),
+    };
+    let cached = cache.and_then(|c| c.load(source.name, parser, &cache_key));
+    let entries = match cached {
+        Some(cached) => {
+            report.cached = true;
+            report.parsed = cached.keys.len() as u64;
+            listed = cached.listed;
+            cached.keys
+        }
+        None => {
+            // Shards are concatenated in name order; a key seen in an earlier
+            // shard keeps its place
+            let mut entries = vec![];
+            let mut seen = HashSet::new();
+            let mut failed = false;
+            let errors_before = errors.len();
+            for file in &files {
+                let mut ctx = ParseContext::new(source.name, file);
+                ctx.dedup = source.dedup;
+                let parsed = input::open(file)
+                    .map_err(|e| ctx.io_error(e))
+                    .and_then(|mut input| source.parser.parse(&mut input, &mut ctx));
+                report.parsed += ctx.parsed;
+                report.duplicates += ctx.duplicates;
+                for e in &ctx.rejected {
+                    report.reject(e);
+                }
+                for e in &ctx.duplicate_records {
+                    report.duplicate(e);
+                }
+                errors.append(&mut ctx.rejected);
+                listed.append(&mut ctx.listed);
+                if source.dedup == DedupPolicy::Error {
+                    *omitted += ctx.duplicates - ctx.duplicate_records.len() as u64;
+                    errors.append(&mut ctx.duplicate_records);
+                }
+                let keys = match parsed {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        // Keep going so every broken shard is reported
+                        errors.push(e);
+                        failed = true;
+                        continue;
+                    }
+                };
+                if files.len() == 1 {
+                    entries = keys;
+                    break;
+                }
+                for key in keys {
+                    if seen.insert(key.clone()) {
+                        entries.push(key);
+                        continue;
+                    }
+                    let duplicate = BuildError::Duplicate {
+                        source: source.name.into(),
+                        key: utils::display_key(&key),
+                        kept_in: source.name.into(),
+                        path: Some(file.as_path().into()),
+                        at: None,
+                    };
+                    report.duplicates += 1;
+                    report.duplicate(&duplicate);
+                    if source.dedup == DedupPolicy::Error {
+                        errors.push(duplicate);
+                    }
+                }
+            }
+            if failed {
+                return Ok(None);
+            }
+            // Cached keys skip parsing, so only cache inputs that parsed
+            // cleanly, keeping rejects and duplicate errors reported on every build
+            if let (Some(cache), true) = (cache, errors.len() == errors_before) {
+                cache.store(source.name, parser, &cache_key, &entries, &listed)?;
+            }
+            entries
+        }
+    };
+
+    // Cached keys are as parsed, so changing the rules doesn't need a re-parse
+    let (entries, variants) = match &source.normalize {
+        Some(rules) => {
+            for (key, _) in &mut listed {
+                *key = rules.apply(key);
+            }
+            normalize(source, rules, entries, errors, report)
+        }
+        None => (entries, vec![]),
+    };
+
+    let count = entries.len() as u64;
+    let mut run: Vec<Pair> = vec![];
+    let mut run_bytes = 0;
+    for (i, key) in entries.into_iter().enumerate() {
+        let pair = (key, source.id_start + i as u64);
+        run_bytes += sort::pair_bytes(&pair);
+        run.push(pair);
+        if run_bytes >= run_limit {
+            run.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            add_run(std::mem::take(&mut run))?;
+            run_bytes = 0;
+        }
+    }
+    run.par_sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    add_run(run)?;
+
+    let entry = SourceEntry {
+        parser: parser.to_string(),
+        count,
+        id_start: source.id_start,
+        id_end: source.id_start + count,
+        input_blake3: Some(input_blake3),
+        normalize: source.normalize.clone(),
+    };
+    Ok(Some(SourceBuilt { entry, variants, listed }))
+}
+
+// Keys that normalize to nothing are filtered out, and keys that normalize
+// to an earlier one are duplicates under the source's dedup policy. Returns
+// the normalized keys in input order and their variant spellings.
+fn normalize(
+    source: &Source,
+    rules: &Normalize,
+    entries: Vec<Vec<u8>>,
+    errors: &mut Vec<BuildError>,
+    report: &mut SourceReport,
+) -> (Vec<Vec<u8>>, Vec<Variant>) {
+    let mut keys = Vec::with_capacity(entries.len());
+    let mut seen = HashSet::new();
+    let mut variants = vec![];
+    for raw in entries {
+        let key = rules.apply(&raw);
+        if key.is_empty() {
+            report.filtered += 1;
+            continue;
+        }
+        variants.extend(rules.variants(&raw, &key).into_iter().map(|v| (v, key.clone())));
+        if seen.insert(key.clone()) {
+            keys.push(key);
+            continue;
+        }
+        let duplicate = BuildError::Duplicate {
+            source: source.name.into(),
+            key: format!("{} (from {})", utils::display_key(&key), utils::display_key(&raw)),
+            kept_in: source.name.into(),
+            path: None,
+            at: None,
+        };
+        report.duplicates += 1;
+        report.duplicate(&duplicate);
+        if source.dedup == DedupPolicy::Error {
+            errors.push(duplicate);
+        }
+    }
+    (keys, variants)
+}
@@ -0,0 +1,49 @@
+use std::{fs, path::Path};
+use memmap2::Mmap;
+
+/// How snapshot files are brought into memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LoadMode {
+    /// Map files read-only, so processes on one host share the page cache.
+    /// Falls back to `Heap` if the file cannot be mapped.
+    #[default]
+    Mmap,
+    /// Read files into a private heap buffer.
+    Heap,
+}
+
+/// Contents of a snapshot file, either mapped or copied onto the heap.
+pub enum Bytes {
+    Mmap(Mmap),
+    Heap(Vec<u8>),
+}
+
+impl Bytes {
+    pub fn load(path: &Path, mode: LoadMode) -> anyhow::Result<Self> {
+        let wrap = |e: std::io::Error| anyhow::anyhow!("{}: {e}", path.display());
+        if mode == LoadMode::Mmap {
+            let file = fs::File::open(path).map_err(wrap)?;
+            // SAFETY: snapshot directories are immutable once published; builds
+            // write new directories instead of modifying files in place, and an
+            // unlinked (pruned) file stays valid for as long as it is mapped.
+            if let Ok(mmap) = unsafe { Mmap::map(&file) } {
+                return Ok(Bytes::Mmap(mmap));
+            }
+        }
+        Ok(Bytes::Heap(fs::read(path).map_err(wrap)?))
+    }
+
+    pub fn is_mmap(&self) -> bool {
+        matches!(self, Bytes::Mmap(_))
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Bytes::Mmap(m) => m,
+            Bytes::Heap(v) => v,
+        }
+    }
+}
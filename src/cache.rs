@@ -0,0 +1,105 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+// Parsed key lists per source, keyed by parser and input BLAKE3, so a build
+// only re-parses sources whose input changed. Entries are
+// `<dir>/<source>/<parser>-<builder version>-c<FORMAT>-<key>.keys`, one per
+// source (all little-endian):
+//   count: u64, count x (len: u32, key bytes)        keys in parse order
+//   repeated (len: u32, key bytes, listed: u64)      listing times
+// The key carries the parser's format version and settings along with the
+// input hash, and the builder version invalidates entries on upgrades.
+pub const CACHE_DIR: &str = ".cache";
+
+// Bumped when the entry layout changes
+const FORMAT: u32 = 2;
+
+/// What a parse produced, as stored in the cache.
+pub struct Cached {
+    pub keys: Vec<Vec<u8>>,
+    /// Listing times from venue fields, so a cache hit carries them too.
+    pub listed: Vec<(Vec<u8>, u64)>,
+}
+
+pub struct SourceCache {
+    dir: PathBuf,
+}
+
+impl SourceCache {
+    pub fn new(dir: &Path) -> Self {
+        Self { dir: dir.to_path_buf() }
+    }
+
+    /// Keys stored for this source, parser and key, or `None` on a miss. A
+    /// damaged entry counts as a miss.
+    pub fn load(&self, source: &str, parser: &str, key: &str) -> Option<Cached> {
+        let bytes = fs::read(self.path(source, parser, key)).ok()?;
+        let mut rest = bytes.as_slice();
+        let count = u64::from_le_bytes(take(&mut rest, 8)?.try_into().unwrap());
+        let mut keys = vec![];
+        for _ in 0..count {
+            keys.push(take_key(&mut rest)?);
+        }
+        let mut listed = vec![];
+        while !rest.is_empty() {
+            let key = take_key(&mut rest)?;
+            listed.push((key, u64::from_le_bytes(take(&mut rest, 8)?.try_into().unwrap())));
+        }
+        Some(Cached { keys, listed })
+    }
+
+    /// Stores a parse and drops older entries of the same source.
+    pub fn store(
+        &self,
+        source: &str,
+        parser: &str,
+        key: &str,
+        keys: &[Vec<u8>],
+        listed: &[(Vec<u8>, u64)],
+    ) -> anyhow::Result<()> {
+        let dir = self.dir.join(source);
+        fs::create_dir_all(&dir)?;
+        let mut bytes = (keys.len() as u64).to_le_bytes().to_vec();
+        for k in keys {
+            bytes.extend_from_slice(&(k.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(k);
+        }
+        for (k, t) in listed {
+            bytes.extend_from_slice(&(k.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(k);
+            bytes.extend_from_slice(&t.to_le_bytes());
+        }
+        // Write then rename, so a crashed build never leaves a partial entry
+        let path = self.path(source, parser, key);
+        let tmp = path.with_extension(format!("tmp-{}", std::process::id()));
+        fs::write(&tmp, bytes)?;
+        fs::rename(&tmp, &path)?;
+
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name.to_string_lossy().ends_with(".keys") && entry.path() != path {
+                match fs::remove_file(entry.path()) {
+                    Ok(()) => {}
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn path(&self, source: &str, parser: &str, key: &str) -> PathBuf {
+        self.dir.join(source).join(format!("{parser}-{}-c{FORMAT}-{key}.keys", env!("CARGO_PKG_VERSION")))
+    }
+}
+
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> Option<&'a [u8]> {
+    let head = rest.get(..n)?;
+    *rest = &rest[n..];
+    Some(head)
+}
+
+fn take_key(rest: &mut &[u8]) -> Option<Vec<u8>> {
+    let len = u32::from_le_bytes(take(rest, 4)?.try_into().unwrap()) as usize;
+    Some(take(rest, len)?.to_vec())
+}
@@ -0,0 +1,272 @@
+use std::{collections::HashMap, fmt::Write as _};
+use crate::TopicMap;
+
+/// One line of an `--allowlist` file: `<source> <key> [CONST_NAME]`.
+/// Keys starting with `0x` are decoded as hex (e.g. Uniswap pool IDs).
+pub struct AllowEntry {
+    pub source: String,
+    pub key: String,
+    pub name: Option<String>,
+}
+
+pub fn parse_allowlist(text: &str) -> anyhow::Result<Vec<AllowEntry>> {
+    let mut entries = vec![];
+    for (lineno, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 2 || fields.len() > 3 {
+            anyhow::bail!("allowlist line {}: expected `<source> <key> [NAME]`", lineno + 1);
+        }
+        entries.push(AllowEntry {
+            source: fields[0].to_string(),
+            key: fields[1].to_string(),
+            name: fields.get(2).map(|s| s.to_string()),
+        });
+    }
+    Ok(entries)
+}
+
+impl AllowEntry {
+    /// The explicit name, or one derived from source and key.
+    pub fn const_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| const_name(&format!("{}_{}", self.source, self.key)))
+    }
+}
+
+/// Rejects explicit names that are not identifiers, and entries whose const
+/// names, or `Topic` variant names if `with_enum`, collide, as the generated
+/// module would not compile.
+pub fn check_names(entries: &[AllowEntry], with_enum: bool) -> anyhow::Result<()> {
+    let mut consts: HashMap<String, &AllowEntry> = HashMap::new();
+    let mut variants: HashMap<String, &AllowEntry> = HashMap::new();
+    for entry in entries {
+        if let Some(name) = &entry.name {
+            if !is_ident(name) {
+                anyhow::bail!("allowlist: `{} {}`: {name:?} is not a Rust identifier", entry.source, entry.key);
+            }
+        }
+        let name = entry.const_name();
+        if let Some(other) = consts.insert(name.clone(), entry) {
+            anyhow::bail!(
+                "allowlist: `{} {}` and `{} {}` both generate const {name}",
+                other.source, other.key, entry.source, entry.key
+            );
+        }
+        if with_enum {
+            let variant = variant_name(&name);
+            if let Some(other) = variants.insert(variant.clone(), entry) {
+                anyhow::bail!(
+                    "allowlist: `{} {}` and `{} {}` both generate Topic::{variant}",
+                    other.source, other.key, entry.source, entry.key
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct Resolved {
+    /// (CONST_NAME, topic ID)
+    pub topics: Vec<(String, u64)>,
+    /// `<source> <key>` of allowlisted topics not in the snapshot.
+    pub missing: Vec<String>,
+}
+
+/// Resolves allowlisted topics against a written snapshot.
+/// Missing topics are reported rather than failing, so the caller can warn;
+/// their constants disappear and dependent code stops compiling.
+/// Two entries resolving to the same ID are an error: the `Topic` enum
+/// would get duplicate discriminants.
+pub fn resolve(entries: &[AllowEntry], map: &TopicMap) -> anyhow::Result<Resolved> {
+    let mut found = vec![];
+    let mut missing = vec![];
+    let mut seen: HashMap<u64, &AllowEntry> = HashMap::new();
+    for entry in entries {
+        let source = map.manifest().sources.get(&entry.source)
+            .ok_or_else(|| anyhow::anyhow!("allowlist: unknown source {:?}", entry.source))?;
+        let key = match entry.key.strip_prefix("0x") {
+            Some(hex) => hex::decode(hex)?,
+            None => entry.key.as_bytes().to_vec(),
+        };
+        let name = entry.const_name();
+
+        let id = map.get(&key)
+            .filter(|id| (source.id_start..source.id_end).contains(id));
+        match id {
+            Some(id) => {
+                if let Some(other) = seen.insert(id, entry) {
+                    anyhow::bail!(
+                        "allowlist: `{} {}` and `{} {}` both resolve to topic {id}",
+                        other.source, other.key, entry.source, entry.key
+                    );
+                }
+                found.push((name, id));
+            }
+            None => missing.push(format!("{} {}", entry.source, entry.key)),
+        }
+    }
+    Ok(Resolved { topics: found, missing })
+}
+
+/// Renders a Rust module with one `TopicId` const per topic and, optionally,
+/// a `Topic` enum with `TryFrom<u64>`. The enum is left out when there are
+/// no topics, as `#[repr(u64)]` on an empty enum does not compile.
+pub fn emit_rust(topics: &[(String, u64)], snapshot: &str, with_enum: bool) -> String {
+    let mut out = String::new();
+    writeln!(out, "// @generated by topic-map-build from snapshot {snapshot}. Do not edit.").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "pub type TopicId = u64;").unwrap();
+    writeln!(out).unwrap();
+    for (name, id) in topics {
+        writeln!(out, "pub const {name}: TopicId = {id};").unwrap();
+    }
+
+    if with_enum && !topics.is_empty() {
+        writeln!(out).unwrap();
+        writeln!(out, "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]").unwrap();
+        writeln!(out, "#[repr(u64)]").unwrap();
+        writeln!(out, "pub enum Topic {{").unwrap();
+        for (name, _) in topics {
+            writeln!(out, "    {} = {name},", variant_name(name)).unwrap();
+        }
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "impl From<Topic> for TopicId {{").unwrap();
+        writeln!(out, "    fn from(t: Topic) -> TopicId {{").unwrap();
+        writeln!(out, "        t as TopicId").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "impl TryFrom<TopicId> for Topic {{").unwrap();
+        writeln!(out, "    type Error = TopicId;").unwrap();
+        writeln!(out).unwrap();
+        writeln!(out, "    fn try_from(id: TopicId) -> Result<Self, TopicId> {{").unwrap();
+        writeln!(out, "        match id {{").unwrap();
+        for (name, _) in topics {
+            writeln!(out, "            {name} => Ok(Topic::{}),", variant_name(name)).unwrap();
+        }
+        writeln!(out, "            _ => Err(id),").unwrap();
+        writeln!(out, "        }}").unwrap();
+        writeln!(out, "    }}").unwrap();
+        writeln!(out, "}}").unwrap();
+    }
+    out
+}
+
+fn is_ident(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {}
+        _ => return false,
+    }
+    name != "_" && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// "binance_ETH-USDT" -> "BINANCE_ETH_USDT"
+fn const_name(raw: &str) -> String {
+    let mut name: String = raw
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+// "BINANCE_ETHUSDT" -> "BinanceEthusdt"
+fn variant_name(const_name: &str) -> String {
+    let name: String = const_name
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let lower = part.to_ascii_lowercase();
+            let mut chars = lower.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("T{name}")
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn entry(source: &str, key: &str, name: Option<&str>) -> AllowEntry {
+        AllowEntry { source: source.into(), key: key.into(), name: name.map(Into::into) }
+    }
+
+    #[test]
+    fn parses_allowlist() {
+        let entries = parse_allowlist("# topics\nbinance ETHUSDT\n\nuniswap 0x1234 POOL # comment\n").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!((entries[0].source.as_str(), entries[0].key.as_str(), entries[0].name.as_deref()), ("binance", "ETHUSDT", None));
+        assert_eq!((entries[1].source.as_str(), entries[1].key.as_str(), entries[1].name.as_deref()), ("uniswap", "0x1234", Some("POOL")));
+        assert_eq!(entries[0].const_name(), "BINANCE_ETHUSDT");
+        assert!(parse_allowlist("binance\n").is_err());
+        assert!(parse_allowlist("binance ETHBTC A B\n").is_err());
+    }
+
+    #[test]
+    fn rejects_bad_and_colliding_names() {
+        assert!(check_names(&[entry("binance", "ETHBTC", Some("ETH_BTC"))], true).is_ok());
+        for bad in ["eth-btc", "1ETH", "_", "ETH.BTC"] {
+            assert!(check_names(&[entry("binance", "ETHBTC", Some(bad))], false).is_err(), "{bad}");
+        }
+        // BINANCE_ETH_USDT both ways
+        let same_const = [entry("binance", "ETH-USDT", None), entry("binance", "ETH_USDT", None)];
+        assert!(check_names(&same_const, false).is_err());
+        // distinct consts, same variant BinanceEthUsdt
+        let same_variant = [entry("binance", "ETH_USDT", None), entry("binance", "X", Some("BINANCE_ETH__USDT"))];
+        assert!(check_names(&same_variant, false).is_ok());
+        assert!(check_names(&same_variant, true).is_err());
+    }
+
+    #[test]
+    fn resolves_within_source_range() {
+        let root = testutil::root("codegen-resolve");
+        let manifest = testutil::manifest(1, &[("binance", 0, 100), ("uniswap", 100, 200)]);
+        let map = testutil::snapshot(&root, manifest, &[("ETHUSDT", 1), ("\x12\x34", 101)], None);
+
+        let entries = [
+            entry("binance", "ETHUSDT", None),
+            entry("uniswap", "0x1234", Some("POOL")),
+            entry("uniswap", "ETHUSDT", None),
+            entry("binance", "SOLUSDT", None),
+        ];
+        let resolved = resolve(&entries, &map).unwrap();
+        assert_eq!(resolved.topics, vec![("BINANCE_ETHUSDT".to_string(), 1), ("POOL".to_string(), 101)]);
+        assert_eq!(resolved.missing, vec!["uniswap ETHUSDT", "binance SOLUSDT"]);
+
+        assert!(resolve(&[entry("kraken", "ETHUSDT", None)], &map).is_err());
+        let twice = [entry("binance", "ETHUSDT", None), entry("binance", "ETHUSDT", Some("ETH"))];
+        assert!(resolve(&twice, &map).is_err());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn emits_consts_and_enum() {
+        let topics = vec![("BINANCE_ETHUSDT".to_string(), 1), ("POOL".to_string(), 101)];
+        let out = emit_rust(&topics, "000001", true);
+        assert!(out.contains("pub const BINANCE_ETHUSDT: TopicId = 1;"));
+        assert!(out.contains("pub const POOL: TopicId = 101;"));
+        assert!(out.contains("    BinanceEthusdt = BINANCE_ETHUSDT,"));
+        assert!(out.contains("            POOL => Ok(Topic::Pool),"));
+        assert!(!emit_rust(&topics, "000001", false).contains("enum Topic"));
+
+        let empty = emit_rust(&[], "000001", true);
+        assert!(empty.contains("pub type TopicId = u64;"));
+        assert!(!empty.contains("enum Topic"));
+    }
+}
@@ -0,0 +1,196 @@
+use std::{collections::BTreeMap, str::FromStr, sync::Arc};
+use crate::error::{BuildError, BuildFailed};
+use crate::manifest::Manifest;
+use crate::report::{SourceReport, MAX_SAMPLES};
+use crate::{utils, Pair};
+
+/// What to do with a key a source emits again. Within a source, `KeepFirst`
+/// and `Alias` both keep one ID per key. Across sources, the occurrence with
+/// the lowest ID wins and the policy of the source losing it applies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DedupPolicy {
+    /// Fail the build, listing the first duplicates and counting the rest.
+    Error,
+    /// Drop the later occurrence; across sources its ID is left unused.
+    #[default]
+    KeepFirst,
+    /// Across sources, keep the later occurrence's ID as an alias: it
+    /// resolves to the key in the reverse index, while the key maps to the
+    /// winning ID.
+    Alias,
+}
+
+impl FromStr for DedupPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "error" => Ok(DedupPolicy::Error),
+            "keep-first" => Ok(DedupPolicy::KeepFirst),
+            "alias" => Ok(DedupPolicy::Alias),
+            other => anyhow::bail!("unknown dedup policy {other:?} (error, keep-first, alias)"),
+        }
+    }
+}
+
+/// Cross-source dedup over a key-sorted stream where equal keys arrive
+/// ordered by ID. Yields each key once, plus repeats for `Alias` sources,
+/// which the writer turns into reverse-index aliases. Errors are collected
+/// and returned together at the end of the stream; past `MAX_SAMPLES` they
+/// are only counted.
+pub struct Dedup<'r, I> {
+    pairs: I,
+    sources: Vec<(Arc<str>, u64, u64, DedupPolicy)>,
+    reports: &'r mut BTreeMap<String, SourceReport>,
+    last: Option<(Vec<u8>, Arc<str>)>,
+    errors: Vec<BuildError>,
+    omitted: u64,
+    done: bool,
+}
+
+impl<'r, I> Dedup<'r, I> {
+    /// Sources without an entry in `policies` use the default policy.
+    pub fn new(
+        pairs: I,
+        manifest: &Manifest,
+        policies: &BTreeMap<String, DedupPolicy>,
+        reports: &'r mut BTreeMap<String, SourceReport>,
+    ) -> Self {
+        let sources = manifest.sources.iter()
+            .map(|(name, s)| {
+                let policy = policies.get(name).copied().unwrap_or_default();
+                (Arc::from(name.as_str()), s.id_start, s.id_end, policy)
+            })
+            .collect();
+        Self { pairs, sources, reports, last: None, errors: vec![], omitted: 0, done: false }
+    }
+
+    fn source_of(&self, id: u64) -> Option<&(Arc<str>, u64, u64, DedupPolicy)> {
+        self.sources.iter().find(|(_, start, end, _)| (*start..*end).contains(&id))
+    }
+}
+
+impl<I> Iterator for Dedup<'_, I>
+where
+    I: Iterator<Item = anyhow::Result<Pair>>,
+{
+    type Item = anyhow::Result<Pair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, id) = match self.pairs.next() {
+                Some(Ok(pair)) => pair,
+                Some(Err(e)) => return Some(Err(e)),
+                None if self.errors.is_empty() || self.done => return None,
+                None => {
+                    self.done = true;
+                    let errors = std::mem::take(&mut self.errors);
+                    return Some(Err(BuildFailed { errors, omitted: self.omitted }.into()));
+                }
+            };
+            let (source, policy) = match self.source_of(id) {
+                Some((name, _, _, policy)) => (name.clone(), *policy),
+                None => (Arc::from("-"), DedupPolicy::default()),
+            };
+            let kept_in = match &self.last {
+                Some((last, kept_in)) if *last == key => kept_in.clone(),
+                _ => {
+                    self.last = Some((key.clone(), source));
+                    return Some(Ok((key, id)));
+                }
+            };
+
+            let duplicate = BuildError::Duplicate {
+                source: source.clone(),
+                key: utils::display_key(&key),
+                kept_in,
+                path: None,
+                at: None,
+            };
+            let report = self.reports.entry(source.to_string()).or_default();
+            report.cross_source_duplicates += 1;
+            report.duplicate(&duplicate);
+            match policy {
+                DedupPolicy::Error if self.errors.len() < MAX_SAMPLES => self.errors.push(duplicate),
+                DedupPolicy::Error => self.omitted += 1,
+                DedupPolicy::KeepFirst => {}
+                DedupPolicy::Alias => {
+                    report.aliased += 1;
+                    return Some(Ok((key, id)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil, writer, TopicMap};
+
+    // binance 0..10, uniswap 10..20
+    fn run(
+        pairs: &[(&str, u64)],
+        policy: DedupPolicy,
+    ) -> (Vec<anyhow::Result<Pair>>, BTreeMap<String, SourceReport>) {
+        let manifest = testutil::manifest(1, &[("binance", 0, 10), ("uniswap", 10, 20)]);
+        let policies = BTreeMap::from([("uniswap".to_string(), policy)]);
+        let mut reports = BTreeMap::new();
+        let pairs = pairs.iter().map(|&(k, id)| Ok((k.as_bytes().to_vec(), id)));
+        let out = Dedup::new(pairs, &manifest, &policies, &mut reports).collect();
+        (out, reports)
+    }
+
+    fn ok(out: &[anyhow::Result<Pair>]) -> Vec<(&str, u64)> {
+        out.iter().filter_map(|r| r.as_ref().ok()).map(|(k, id)| (std::str::from_utf8(k).unwrap(), *id)).collect()
+    }
+
+    const PAIRS: &[(&str, u64)] = &[("A", 0), ("B", 1), ("B", 11), ("C", 12), ("D", 2), ("D", 13)];
+
+    #[test]
+    fn keep_first_keeps_lowest_id() {
+        let (out, reports) = run(PAIRS, DedupPolicy::KeepFirst);
+        assert_eq!(ok(&out), vec![("A", 0), ("B", 1), ("C", 12), ("D", 2)]);
+        assert_eq!(out.len(), 4);
+        assert_eq!(reports["uniswap"].cross_source_duplicates, 2);
+        assert_eq!(reports["uniswap"].duplicate_samples[0].kept_in, "binance");
+    }
+
+    #[test]
+    fn errors_come_after_the_stream() {
+        let (out, reports) = run(PAIRS, DedupPolicy::Error);
+        assert_eq!(out.len(), 5);
+        assert_eq!(ok(&out[..4]), vec![("A", 0), ("B", 1), ("C", 12), ("D", 2)]);
+        let failed = out[4].as_ref().unwrap_err().downcast_ref::<BuildFailed>().unwrap();
+        assert_eq!((failed.errors.len(), failed.omitted), (2, 0));
+        assert!(matches!(&failed.errors[0], BuildError::Duplicate { source, kept_in, .. } if &**source == "uniswap" && &**kept_in == "binance"));
+        assert_eq!(reports["uniswap"].cross_source_duplicates, 2);
+
+        // Past the sample, duplicates are only counted
+        let keys: Vec<String> = (0..MAX_SAMPLES + 5).map(|i| format!("K{i:03}")).collect();
+        let mut many = vec![];
+        for (i, key) in keys.iter().enumerate() {
+            many.push((key.as_str(), 0));
+            many.push((key.as_str(), 10 + i as u64 % 10));
+        }
+        let (out, _) = run(&many, DedupPolicy::Error);
+        let failed = out.last().unwrap().as_ref().unwrap_err().downcast_ref::<BuildFailed>().unwrap();
+        assert_eq!((failed.errors.len(), failed.omitted), (MAX_SAMPLES, 5));
+    }
+
+    #[test]
+    fn aliases_reach_the_reverse_index() {
+        let (out, reports) = run(PAIRS, DedupPolicy::Alias);
+        assert_eq!(ok(&out), vec![("A", 0), ("B", 1), ("B", 11), ("C", 12), ("D", 2), ("D", 13)]);
+        assert_eq!(reports["uniswap"].aliased, 2);
+
+        let root = testutil::root("dedup-alias");
+        let mut manifest = testutil::manifest(1, &[("binance", 0, 10), ("uniswap", 10, 20)]);
+        let written = writer::write_snapshot(&root, &mut manifest, out, vec![], None, false).unwrap();
+        let map = TopicMap::open(&written.dir).unwrap();
+        assert_eq!(map.get("B"), Some(1));
+        assert_eq!(map.key_of(11).as_deref(), Some(&b"B"[..]));
+        assert_eq!(map.key_of(13).as_deref(), Some(&b"D"[..]));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
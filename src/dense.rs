@@ -0,0 +1,98 @@
+use std::{fs, io::BufWriter, path::Path};
+use fst::{Map, MapBuilder, Streamer};
+use crate::bytes::{Bytes, LoadMode};
+
+// Dense IDs are a compact 0..N u32 space across all sources, for consumers
+// that index a Vec by topic ID. `topic.dense.fst` maps key -> dense ID and
+// `topic.ids.bin` maps dense ID -> stable external (ranged) ID.
+pub const DENSE_FST_FILE: &str = "topic.dense.fst";
+pub const DENSE_IDS_FILE: &str = "topic.ids.bin";
+
+/// Writes both dense tables for a written map into `dir` and returns the
+/// entry count. Dense IDs follow external ID order, so sources keep their
+/// relative order and each source stays contiguous in the dense space.
+pub fn write<D: AsRef<[u8]>>(dir: &Path, map: &Map<D>) -> anyhow::Result<u64> {
+    if map.len() > u32::MAX as usize {
+        anyhow::bail!("{} entries do not fit in a u32 dense ID space", map.len());
+    }
+
+    let mut external_ids: Vec<u64> = Vec::with_capacity(map.len());
+    let mut stream = map.stream();
+    while let Some((_, id)) = stream.next() {
+        external_ids.push(id);
+    }
+    external_ids.sort_unstable();
+    write_ids(&dir.join(DENSE_IDS_FILE), &external_ids)?;
+
+    // A key's dense ID is the rank of its external ID; a second pass over the
+    // map yields keys in order for the dense FST
+    let mut builder = MapBuilder::new(BufWriter::new(fs::File::create(dir.join(DENSE_FST_FILE))?))?;
+    let mut stream = map.stream();
+    while let Some((key, id)) = stream.next() {
+        let dense = external_ids.binary_search(&id).unwrap();
+        builder.insert(key, dense as u64)?;
+    }
+    builder.finish()?;
+    Ok(external_ids.len() as u64)
+}
+
+pub fn write_ids(path: &Path, external_ids: &[u64]) -> anyhow::Result<()> {
+    let mut bytes = Vec::with_capacity(external_ids.len() * 8);
+    for id in external_ids {
+        bytes.extend_from_slice(&id.to_le_bytes());
+    }
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Dense ID -> external ID table, stored as little-endian u64s.
+pub struct DenseIds {
+    bytes: Bytes,
+}
+
+impl DenseIds {
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        Self::open(path, LoadMode::default())
+    }
+
+    pub fn open(path: &Path, mode: LoadMode) -> anyhow::Result<Self> {
+        let bytes = Bytes::load(path, mode)?;
+        let len = bytes.as_ref().len();
+        if len % 8 != 0 {
+            anyhow::bail!("{}: length {len} is not a multiple of 8", path.display());
+        }
+        Ok(Self { bytes })
+    }
+
+    /// Number of dense slots a consumer needs to allocate.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.as_ref().len() / 8
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    pub fn external(&self, dense: u32) -> Option<u64> {
+        let start = dense as usize * 8;
+        let chunk = self.bytes.as_ref().get(start..start + 8)?;
+        Some(u64::from_le_bytes(chunk.try_into().unwrap()))
+    }
+
+    // External IDs are written in ascending order, so this is a binary search.
+    pub fn dense(&self, external: u64) -> Option<u32> {
+        let (mut lo, mut hi) = (0usize, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.external(mid as u32)?.cmp(&external) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid as u32),
+            }
+        }
+        None
+    }
+}
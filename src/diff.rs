@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+use fst::{map::OpBuilder, Streamer};
+use serde_json::Value;
+use crate::manifest::Manifest;
+use crate::TopicMap;
+
+/// Differences between two snapshots, from one ordered pass over both FSTs.
+#[derive(Debug, Default)]
+pub struct SnapshotDiff {
+    /// (key, new ID)
+    pub added: Vec<(Vec<u8>, u64)>,
+    /// (key, old ID)
+    pub removed: Vec<(Vec<u8>, u64)>,
+    /// (key, old ID, new ID). Consumers key state by topic ID, so any entry
+    /// here is a red flag.
+    pub changed: Vec<(Vec<u8>, u64, u64)>,
+    pub per_source: BTreeMap<String, SourceDiff>,
+    pub metadata: Vec<MetadataChange>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SourceDiff {
+    pub added: u64,
+    pub removed: u64,
+    pub changed: u64,
+}
+
+/// A manifest field that differs, e.g. `sources.binance.count`.
+#[derive(Debug)]
+pub struct MetadataChange {
+    pub field: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+pub fn diff(old: &TopicMap, new: &TopicMap) -> SnapshotDiff {
+    let mut diff = SnapshotDiff::default();
+    let source = |m: &TopicMap, id: u64| m.manifest().source_of(id).unwrap_or("-").to_string();
+
+    let mut union = OpBuilder::new().add(old.as_fst()).add(new.as_fst()).union();
+    while let Some((key, values)) = union.next() {
+        let old_id = values.iter().find(|v| v.index == 0).map(|v| v.value);
+        let new_id = values.iter().find(|v| v.index == 1).map(|v| v.value);
+        match (old_id, new_id) {
+            (Some(o), Some(n)) if o != n => {
+                diff.per_source.entry(source(new, n)).or_default().changed += 1;
+                diff.changed.push((key.to_vec(), o, n));
+            }
+            (Some(_), Some(_)) => {}
+            (None, Some(n)) => {
+                diff.per_source.entry(source(new, n)).or_default().added += 1;
+                diff.added.push((key.to_vec(), n));
+            }
+            (Some(o), None) => {
+                diff.per_source.entry(source(old, o)).or_default().removed += 1;
+                diff.removed.push((key.to_vec(), o));
+            }
+            (None, None) => unreachable!("union yields keys present in some input"),
+        }
+    }
+
+    diff.metadata = diff_manifests(old.manifest(), new.manifest());
+    diff
+}
+
+/// Field-by-field differences, ignoring the per-build timestamp and file list.
+pub fn diff_manifests(old: &Manifest, new: &Manifest) -> Vec<MetadataChange> {
+    let mut old = serde_json::to_value(old).unwrap_or_default();
+    let mut new = serde_json::to_value(new).unwrap_or_default();
+    for v in [&mut old, &mut new] {
+        if let Some(obj) = v.as_object_mut() {
+            obj.remove("built_at");
+            obj.remove("files");
+        }
+    }
+    let mut changes = vec![];
+    diff_values("", &old, &new, &mut changes);
+    changes
+}
+
+fn diff_values(path: &str, old: &Value, new: &Value, out: &mut Vec<MetadataChange>) {
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for k in keys {
+                let field = if path.is_empty() { k.clone() } else { format!("{path}.{k}") };
+                diff_values(
+                    &field,
+                    a.get(k).unwrap_or(&Value::Null),
+                    b.get(k).unwrap_or(&Value::Null),
+                    out,
+                );
+            }
+        }
+        (a, b) if a != b => out.push(MetadataChange {
+            field: path.to_string(),
+            old: a.clone(),
+            new: b.clone(),
+        }),
+        _ => {}
+    }
+}
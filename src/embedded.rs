@@ -0,0 +1,9 @@
+// Topic map compiled into the binary by build.rs (feature `embedded`), for
+// services that want zero file I/O and a static, immutable topic set.
+// Regenerated whenever the embedded snapshot changes.
+include!(concat!(env!("OUT_DIR"), "/embedded_topic_map.rs"));
+
+#[inline]
+pub fn get(key: &[u8]) -> Option<u64> {
+    MAP.get(key).copied()
+}
@@ -0,0 +1,122 @@
+use std::{fmt, path::Path, sync::Arc};
+
+/// Where in an input file a problem was found. `pointer` is an RFC 6901 JSON
+/// pointer; `line`/`column` are 1-based and point at the end of the value.
+#[derive(Debug, Clone, Default)]
+pub struct Position {
+    pub pointer: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A problem with one source input. Only `InvalidRecord` can be skipped;
+/// the others leave the source unusable. Source and path are shared, as a
+/// bad input can produce one error per record.
+#[derive(Debug, Clone)]
+pub enum BuildError {
+    /// The input could not be found, opened or decompressed.
+    Io { source: Arc<str>, path: Arc<Path>, message: String },
+    /// The input is not valid JSON; nothing after `at` can be read.
+    Syntax { source: Arc<str>, path: Arc<Path>, at: Position, message: String },
+    /// Valid JSON, but not the document shape the parser expects.
+    Shape { source: Arc<str>, path: Arc<Path>, at: Position, message: String },
+    /// A record the parser recognized but could not use.
+    InvalidRecord { source: Arc<str>, path: Arc<Path>, at: Position, value: String, reason: String },
+    /// A key already emitted by `kept_in` (possibly the same source), from a
+    /// source whose dedup policy is `error`. Location is known for duplicates
+    /// within one input file.
+    Duplicate { source: Arc<str>, key: String, kept_in: Arc<str>, path: Option<Arc<Path>>, at: Option<Position> },
+}
+
+impl BuildError {
+    pub fn io(source: Arc<str>, path: Arc<Path>, e: impl fmt::Display) -> Self {
+        // Input helpers already prefix their errors with the path
+        let message = e.to_string();
+        let message = message.strip_prefix(&format!("{}: ", path.display())).unwrap_or(&message).to_string();
+        BuildError::Io { source, path, message }
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, BuildError::InvalidRecord { .. })
+    }
+
+    pub fn source_name(&self) -> &str {
+        match self {
+            BuildError::Io { source, .. }
+            | BuildError::Syntax { source, .. }
+            | BuildError::Shape { source, .. }
+            | BuildError::InvalidRecord { source, .. }
+            | BuildError::Duplicate { source, .. } => source,
+        }
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = |f: &mut fmt::Formatter, source: &str, path: &Path, at: &Position| -> fmt::Result {
+            write!(f, "{source}: {}:{}:{}", path.display(), at.line, at.column)?;
+            if !at.pointer.is_empty() {
+                write!(f, " ({})", at.pointer)?;
+            }
+            Ok(())
+        };
+        match self {
+            BuildError::Io { source, path, message } => {
+                write!(f, "{source}: {}: {message}", path.display())
+            }
+            BuildError::Syntax { source, path, at: pos, message } => {
+                at(f, source, path, pos)?;
+                write!(f, ": invalid JSON: {message}")
+            }
+            BuildError::Shape { source, path, at: pos, message } => {
+                at(f, source, path, pos)?;
+                write!(f, ": unexpected document shape: {message}")
+            }
+            BuildError::InvalidRecord { source, path, at: pos, value, reason } => {
+                at(f, source, path, pos)?;
+                write!(f, ": invalid record {value:?}: {reason}")
+            }
+            BuildError::Duplicate { source, key, kept_in, path, at: pos } => {
+                match (path, pos) {
+                    (Some(path), Some(pos)) => at(f, source, path, pos)?,
+                    (Some(path), None) => write!(f, "{source}: {}", path.display())?,
+                    _ => write!(f, "{source}")?,
+                }
+                if kept_in == source {
+                    write!(f, ": duplicate key {key}")
+                } else {
+                    write!(f, ": duplicate key {key}, already in {kept_in}")
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+/// Every error from a failed build, so callers can report them all at once.
+/// Duplicates beyond a sample are only counted, in `omitted`.
+#[derive(Debug)]
+pub struct BuildFailed {
+    pub errors: Vec<BuildError>,
+    pub omitted: u64,
+}
+
+// Errors listed in the message; the rest are only counted
+const SHOWN: usize = 20;
+
+impl fmt::Display for BuildFailed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let n = self.errors.len() as u64 + self.omitted;
+        write!(f, "build failed with {n} error{}:", if n == 1 { "" } else { "s" })?;
+        for e in self.errors.iter().take(SHOWN) {
+            write!(f, "\n  {e}")?;
+        }
+        if n > SHOWN as u64 {
+            write!(f, "\n  ... and {} more", n - SHOWN as u64)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BuildFailed {}
@@ -0,0 +1,163 @@
+use std::fmt;
+use crate::diff::SnapshotDiff;
+use crate::manifest::Manifest;
+
+/// Thresholds a new build must meet against the snapshot it replaces, so a
+/// truncated upstream dump can't silently ship a tiny map. `None` disables a
+/// check.
+#[derive(Debug, Clone)]
+pub struct Guardrails {
+    /// Max share of a source's previous keys that may disappear, in percent.
+    pub max_removed_pct: Option<f64>,
+    /// Max added + removed + re-IDed keys across all sources.
+    pub max_churn: Option<u64>,
+    /// Min keys per source. Also checked on the first build.
+    pub min_count: Option<u64>,
+}
+
+impl Default for Guardrails {
+    fn default() -> Self {
+        Self { max_removed_pct: Some(10.0), max_churn: None, min_count: Some(1) }
+    }
+}
+
+/// One failed check; `source` is `None` for whole-map checks.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub source: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "{source}: {}", self.message),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Guardrails {
+    /// Checks `new` against the previous snapshot, given as its manifest and
+    /// the diff from it (both `None` on the first build).
+    pub fn check(&self, previous: Option<(&Manifest, &SnapshotDiff)>, new: &Manifest) -> Vec<Violation> {
+        let mut violations = vec![];
+
+        if let Some(min) = self.min_count {
+            for (name, entry) in &new.sources {
+                if entry.count < min {
+                    violations.push(Violation {
+                        source: Some(name.clone()),
+                        message: format!("{} keys, minimum is {min}", entry.count),
+                    });
+                }
+            }
+        }
+
+        let Some((old, diff)) = previous else {
+            return violations;
+        };
+        if let Some(max_pct) = self.max_removed_pct {
+            for (name, entry) in &old.sources {
+                let removed = diff.per_source.get(name).map_or(0, |d| d.removed);
+                if entry.count == 0 || removed == 0 {
+                    continue;
+                }
+                let pct = removed as f64 * 100.0 / entry.count as f64;
+                if pct > max_pct {
+                    violations.push(Violation {
+                        source: Some(name.clone()),
+                        message: format!(
+                            "{removed} of {} keys removed ({pct:.1}%), maximum is {max_pct}%",
+                            entry.count
+                        ),
+                    });
+                }
+            }
+        }
+        if let Some(max) = self.max_churn {
+            let churn = (diff.added.len() + diff.removed.len() + diff.changed.len()) as u64;
+            if churn > max {
+                violations.push(Violation {
+                    source: None,
+                    message: format!(
+                        "{churn} keys changed ({} added, {} removed, {} re-IDed), maximum is {max}",
+                        diff.added.len(), diff.removed.len(), diff.changed.len()
+                    ),
+                });
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::SourceDiff;
+    use crate::testutil;
+
+    fn manifest(binance: u64, uniswap: u64) -> Manifest {
+        let mut manifest = testutil::manifest(1, &[("binance", 0, 1000), ("uniswap", 1000, 2000)]);
+        manifest.sources.get_mut("binance").unwrap().count = binance;
+        manifest.sources.get_mut("uniswap").unwrap().count = uniswap;
+        manifest
+    }
+
+    fn removed(source: &str, n: u64) -> SnapshotDiff {
+        SnapshotDiff {
+            removed: (0..n).map(|i| (format!("K{i}").into_bytes(), i)).collect(),
+            per_source: [(source.to_string(), SourceDiff { removed: n, ..SourceDiff::default() })].into(),
+            ..SnapshotDiff::default()
+        }
+    }
+
+    fn sources(violations: &[Violation]) -> Vec<Option<&str>> {
+        violations.iter().map(|v| v.source.as_deref()).collect()
+    }
+
+    #[test]
+    fn removal_percentage_is_per_source() {
+        let guard = Guardrails::default();
+        let old = manifest(100, 100);
+        assert!(guard.check(Some((&old, &removed("binance", 10))), &manifest(90, 100)).is_empty());
+
+        let violations = guard.check(Some((&old, &removed("binance", 11))), &manifest(89, 100));
+        assert_eq!(sources(&violations), vec![Some("binance")]);
+        assert_eq!(violations[0].to_string(), "binance: 11 of 100 keys removed (11.0%), maximum is 10%");
+
+        let off = Guardrails { max_removed_pct: None, ..Guardrails::default() };
+        assert!(off.check(Some((&old, &removed("binance", 50))), &manifest(50, 100)).is_empty());
+    }
+
+    #[test]
+    fn churn_counts_every_change() {
+        let guard = Guardrails { max_churn: Some(5), ..Guardrails::default() };
+        let old = manifest(100, 100);
+        let mut diff = removed("binance", 3);
+        diff.added = vec![(b"N".to_vec(), 99)];
+        diff.changed = vec![(b"C".to_vec(), 1, 2)];
+        assert!(guard.check(Some((&old, &diff)), &manifest(98, 100)).is_empty());
+
+        diff.added.push((b"M".to_vec(), 98));
+        let violations = guard.check(Some((&old, &diff)), &manifest(99, 100));
+        assert_eq!(sources(&violations), vec![None]);
+        assert_eq!(violations[0].message, "6 keys changed (2 added, 3 removed, 1 re-IDed), maximum is 5");
+    }
+
+    #[test]
+    fn min_count_applies_to_every_source() {
+        let guard = Guardrails { min_count: Some(10), ..Guardrails::default() };
+        let old = manifest(100, 100);
+        let violations = guard.check(Some((&old, &SnapshotDiff::default())), &manifest(100, 9));
+        assert_eq!(sources(&violations), vec![Some("uniswap")]);
+        assert_eq!(violations[0].message, "9 keys, minimum is 10");
+    }
+
+    #[test]
+    fn first_build_only_checks_min_count() {
+        let guard = Guardrails { max_churn: Some(0), ..Guardrails::default() };
+        assert!(guard.check(None, &manifest(5, 5)).is_empty());
+        assert_eq!(sources(&guard.check(None, &manifest(0, 5))), vec![Some("binance")]);
+    }
+}
@@ -0,0 +1,171 @@
+use std::{path::{Path, PathBuf}, sync::Arc, thread, time::Duration};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use arc_swap::{ArcSwap, Guard};
+use crate::{snapshot, TopicMap};
+
+/// Shared, hot-reloadable `TopicMap` for long-running services.
+///
+/// Lookups load the current map without locking; a reload verifies the new
+/// snapshot and swaps it in, while readers holding the old `Arc` keep using
+/// it until they drop it.
+#[derive(Clone)]
+pub struct TopicMapHandle {
+    root: Arc<PathBuf>,
+    map: Arc<ArcSwap<TopicMap>>,
+}
+
+impl TopicMapHandle {
+    /// `root` is a snapshot root with a `current` pointer.
+    pub fn open(root: &Path) -> anyhow::Result<Self> {
+        let map = TopicMap::open_verified(root)?;
+        Ok(Self {
+            root: Arc::new(root.to_path_buf()),
+            map: Arc::new(ArcSwap::from_pointee(map)),
+        })
+    }
+
+    /// Cheap snapshot of the current map, for a batch of lookups.
+    #[inline]
+    pub fn load(&self) -> Guard<Arc<TopicMap>> {
+        self.map.load()
+    }
+
+    #[inline]
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<u64> {
+        self.map.load().get(key)
+    }
+
+    /// See `TopicMap::normalize_and_get`.
+    #[inline]
+    pub fn normalize_and_get<K: AsRef<[u8]>>(&self, key: K) -> Option<u64> {
+        self.map.load().normalize_and_get(key)
+    }
+
+    /// Swaps in the snapshot `current` points to if it changed. Returns
+    /// whether a new map was installed; on error the old map stays.
+    pub fn reload(&self) -> anyhow::Result<bool> {
+        let target = snapshot::resolve(&self.root)?;
+        if target == self.map.load().dir() {
+            return Ok(false);
+        }
+        let map = TopicMap::open_verified(&target)?;
+        self.map.store(Arc::new(map));
+        Ok(true)
+    }
+
+    /// Polls the `current` pointer every `interval` on a background thread,
+    /// passing each reload or failed reload to `on_reload`. Stops when the
+    /// returned `Watcher` is dropped.
+    pub fn watch<F>(&self, interval: Duration, mut on_reload: F) -> Watcher
+    where
+        F: FnMut(Reload) + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = self.clone();
+        let thread = thread::spawn(move || {
+            let mut failed: Option<PathBuf> = None;
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                // Report a bad snapshot once rather than on every tick
+                let target = snapshot::resolve(&handle.root).ok();
+                if target.is_some() && target == failed {
+                    continue;
+                }
+                match handle.reload() {
+                    Ok(true) => {
+                        on_reload(Reload::Installed(handle.load().dir()));
+                        failed = None;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        on_reload(Reload::Failed(&e));
+                        failed = target;
+                    }
+                }
+            }
+        });
+        Watcher { stop: Some(stop), thread: Some(thread) }
+    }
+}
+
+/// A `watch` tick that changed something. After `Failed` the current map
+/// is kept, and a failure is reported once per bad snapshot.
+#[derive(Debug)]
+pub enum Reload<'a> {
+    /// The snapshot directory now being served.
+    Installed(&'a Path),
+    Failed(&'a anyhow::Error),
+}
+
+pub struct Watcher {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        // Dropping the sender wakes the thread immediately
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil, topic_map::MAP_FILE, writer};
+
+    fn publish(root: &Path, keys: &[(&str, u64)]) -> TopicMap {
+        testutil::snapshot(root, testutil::manifest(1, &[("binance", 0, 100)]), keys, None)
+    }
+
+    #[test]
+    fn held_guard_keeps_the_old_map() {
+        let root = testutil::root("handle-reload");
+        publish(&root, &[("ETH", 1)]);
+        let handle = TopicMapHandle::open(&root).unwrap();
+        assert!(!handle.reload().unwrap());
+
+        let old = handle.load();
+        let new = publish(&root, &[("BTC", 2), ("ETH", 3)]);
+        assert!(handle.reload().unwrap());
+        assert_eq!((old.get("ETH"), old.get("BTC")), (Some(1), None));
+        assert_eq!((handle.get("ETH"), handle.get("BTC")), (Some(3), Some(2)));
+        assert_eq!(handle.load().dir(), new.dir());
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn watcher_reports_reloads_and_failures() {
+        let root = testutil::root("handle-watch");
+        publish(&root, &[("ETH", 1)]);
+        let handle = TopicMapHandle::open(&root).unwrap();
+        let (tx, rx) = mpsc::channel();
+        let watcher = handle.watch(Duration::from_millis(10), move |reload| {
+            let _ = tx.send(match reload {
+                Reload::Installed(dir) => Ok(dir.to_path_buf()),
+                Reload::Failed(e) => Err(e.to_string()),
+            });
+        });
+        let next = || rx.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        let new = publish(&root, &[("ETH", 2)]);
+        assert_eq!(next(), Ok(new.dir().to_path_buf()));
+        assert_eq!(handle.get("ETH"), Some(2));
+
+        // A corrupt snapshot is reported once and the served map stays
+        let mut manifest = testutil::manifest(1, &[("binance", 0, 100)]);
+        let pairs = vec![Ok((b"ETH".to_vec(), 3))];
+        let bad = writer::stage(&root, &mut manifest, pairs, vec![], None, false).unwrap();
+        let mut bytes = std::fs::read(bad.dir.join(MAP_FILE)).unwrap();
+        bytes[0] ^= 0xff;
+        std::fs::write(bad.dir.join(MAP_FILE), bytes).unwrap();
+        snapshot::publish(&root, &bad.name).unwrap();
+        assert!(next().is_err());
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        assert_eq!(handle.get("ETH"), Some(2));
+        drop(watcher);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
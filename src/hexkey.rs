@@ -0,0 +1,185 @@
+use std::str::FromStr;
+use alloy::primitives::Address;
+
+/// Which hex strings are keys. The default accepts `0x` + 64 hex digits (a
+/// 32-byte pool ID); the options add other spellings of it and 20-byte
+/// addresses. Keys are stored as raw bytes whatever the spelling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexKeys {
+    /// Accept a `0X` prefix.
+    pub upper_prefix: bool,
+    /// Accept hex digits without a prefix.
+    pub unprefixed: bool,
+    /// Accept 20-byte addresses (40 hex digits).
+    pub addresses: bool,
+    /// Reject mixed-case addresses that fail their EIP-55 checksum. All
+    /// lowercase or all uppercase addresses carry no checksum.
+    pub checksum: bool,
+}
+
+/// What `HexKeys::recognize` made of a string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recognized {
+    Key(Vec<u8>),
+    /// Close enough to a key that it is likely a malformed one, e.g. 65 hex
+    /// digits or a bad checksum; the reason says what is wrong.
+    NearMiss(String),
+    /// Not a hex key.
+    None,
+}
+
+impl Default for HexKeys {
+    fn default() -> Self {
+        Self { upper_prefix: false, unprefixed: false, addresses: false, checksum: true }
+    }
+}
+
+// Digit counts within this distance of a key length are near misses
+const SLACK: usize = 2;
+
+impl HexKeys {
+    /// Every spelling, for keys typed by users.
+    pub fn all() -> Self {
+        Self { upper_prefix: true, unprefixed: true, addresses: true, checksum: true }
+    }
+
+    pub fn recognize(&self, s: &str) -> Recognized {
+        let (prefix, digits) = match s.get(..2) {
+            Some(p @ ("0x" | "0X")) => (Some(p), &s[2..]),
+            _ => (None, s),
+        };
+        let all_hex = !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_hexdigit());
+        let len = digits.len();
+        let key_len = len == 64 || (self.addresses && len == 40);
+
+        let Some(prefix) = prefix else {
+            // Plain words can be short hex, so only exact lengths count
+            return match (all_hex && key_len, self.unprefixed) {
+                (true, true) => self.decode(digits),
+                (true, false) if len == 64 => Recognized::NearMiss("32-byte hex without 0x prefix".into()),
+                _ => Recognized::None,
+            };
+        };
+        if !all_hex {
+            return match key_len {
+                true => Recognized::NearMiss("non-hex digits".into()),
+                false => Recognized::None,
+            };
+        }
+        if key_len {
+            return match prefix == "0X" && !self.upper_prefix {
+                true => Recognized::NearMiss("uppercase 0X prefix".into()),
+                false => self.decode(digits),
+            };
+        }
+        let near = |target: usize| len.abs_diff(target) <= SLACK;
+        if near(64) || (self.addresses && near(40)) {
+            let expected = if self.addresses { "64 or 40" } else { "64" };
+            return Recognized::NearMiss(format!("{len} hex digits, expected {expected}"));
+        }
+        Recognized::None
+    }
+
+    fn decode(&self, digits: &str) -> Recognized {
+        if digits.len() == 40 && self.checksum {
+            let mixed = digits.bytes().any(|b| b.is_ascii_lowercase())
+                && digits.bytes().any(|b| b.is_ascii_uppercase());
+            if mixed && Address::parse_checksummed(format!("0x{digits}"), None).is_err() {
+                return Recognized::NearMiss("address fails its EIP-55 checksum".into());
+            }
+        }
+        match hex::decode(digits) {
+            Ok(bytes) => Recognized::Key(bytes),
+            Err(e) => Recognized::NearMiss(e.to_string()),
+        }
+    }
+
+    /// Cache key suffix: the options change which keys a parse returns.
+    pub fn tag(&self) -> String {
+        let flags = [self.upper_prefix, self.unprefixed, self.addresses, self.checksum];
+        flags.iter().map(|&f| if f { '1' } else { '0' }).collect()
+    }
+}
+
+impl FromStr for HexKeys {
+    type Err = anyhow::Error;
+
+    /// Comma-separated `upper-prefix`, `unprefixed`, `addresses` and
+    /// `no-checksum`, added to the default.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut keys = HexKeys::default();
+        for option in s.split(',') {
+            match option {
+                "upper-prefix" => keys.upper_prefix = true,
+                "unprefixed" => keys.unprefixed = true,
+                "addresses" => keys.addresses = true,
+                "no-checksum" => keys.checksum = false,
+                other => anyhow::bail!(
+                    "unknown hex key option {other:?} (upper-prefix, unprefixed, addresses, no-checksum)"
+                ),
+            }
+        }
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL: &str = "000a193942d54b2c53c150653b377006504bcd2892846e45495a9a0af1f45e3e";
+    // EIP-55 example address
+    const ADDRESS: &str = "5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+
+    fn is_key(r: Recognized) -> bool {
+        matches!(r, Recognized::Key(_))
+    }
+
+    fn is_near_miss(r: Recognized) -> bool {
+        matches!(r, Recognized::NearMiss(_))
+    }
+
+    #[test]
+    fn default_accepts_prefixed_pool_ids() {
+        let hex = HexKeys::default();
+        assert_eq!(hex.recognize(&format!("0x{POOL}")), Recognized::Key(hex::decode(POOL).unwrap()));
+        assert!(is_near_miss(hex.recognize(&format!("0X{POOL}"))));
+        assert!(is_near_miss(hex.recognize(POOL)));
+        assert!(is_near_miss(hex.recognize(&format!("0x{POOL}a"))));
+        assert!(is_near_miss(hex.recognize(&format!("0x{}zz", &POOL[2..]))));
+        assert_eq!(hex.recognize(&format!("0x{ADDRESS}")), Recognized::None);
+        assert_eq!(hex.recognize("0x1234"), Recognized::None);
+        assert_eq!(hex.recognize("BTCUSDT"), Recognized::None);
+        assert_eq!(hex.recognize("cafe"), Recognized::None);
+    }
+
+    #[test]
+    fn options_add_spellings() {
+        let hex: HexKeys = "upper-prefix,unprefixed,addresses".parse().unwrap();
+        assert!(is_key(hex.recognize(&format!("0X{POOL}"))));
+        assert!(is_key(hex.recognize(POOL)));
+        assert!(is_key(hex.recognize(&format!("0x{ADDRESS}"))));
+        assert!(is_key(hex.recognize(ADDRESS)));
+        assert!(is_near_miss(hex.recognize(&format!("0x{}", &ADDRESS[1..]))));
+        assert!("bogus".parse::<HexKeys>().is_err());
+    }
+
+    #[test]
+    fn mixed_case_addresses_need_a_valid_checksum() {
+        let hex = HexKeys::all();
+        let bad = ADDRESS.replace('a', "A");
+        assert!(is_key(hex.recognize(&format!("0x{ADDRESS}"))));
+        assert!(is_near_miss(hex.recognize(&format!("0x{bad}"))));
+        assert!(is_key(hex.recognize(&format!("0x{}", ADDRESS.to_lowercase()))));
+        assert!(is_key(hex.recognize(&format!("0x{}", ADDRESS.to_uppercase()))));
+        let unchecked = HexKeys { checksum: false, ..HexKeys::all() };
+        assert!(is_key(unchecked.recognize(&format!("0x{bad}"))));
+    }
+
+    #[test]
+    fn tag_tracks_options() {
+        assert_eq!(HexKeys::default().tag(), "0001");
+        assert_eq!(HexKeys::all().tag(), "1111");
+        assert_eq!("no-checksum".parse::<HexKeys>().unwrap().tag(), "0000");
+    }
+}
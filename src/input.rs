@@ -0,0 +1,129 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+};
+use flate2::read::MultiGzDecoder;
+
+// Source inputs: a single file, a directory of shards, or a `*`/`?` pattern
+// in the file name (e.g. `pools/2024-*.json.zst`). Each file is decompressed
+// by its magic bytes, falling back to the extension.
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// The files behind a source path, in name order. Hidden files are skipped.
+pub fn expand(path: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let p = Path::new(path);
+    let name = p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let (dir, pattern) = if p.is_dir() {
+        (p.to_path_buf(), "*".to_string())
+    } else if name.contains(['*', '?']) {
+        let parent = p.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        (parent.to_path_buf(), name)
+    } else {
+        return Ok(vec![p.to_path_buf()]);
+    };
+
+    let mut files = vec![];
+    for entry in fs::read_dir(&dir).map_err(|e| anyhow::anyhow!("{}: {e}", dir.display()))? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if !file_name.starts_with('.') && entry.file_type()?.is_file() && matches(&pattern, &file_name) {
+            files.push(entry.path());
+        }
+    }
+    if files.is_empty() {
+        anyhow::bail!("{path}: no input files");
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Opens `path`, transparently decompressing gzip and zstd.
+pub fn open(path: &Path) -> anyhow::Result<Box<dyn Read + Send>> {
+    let file = fs::File::open(path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    let mut reader = BufReader::new(file);
+    let head = reader.fill_buf()?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if head.starts_with(GZIP_MAGIC) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    } else if head.starts_with(ZSTD_MAGIC) {
+        Ok(Box::new(BufReader::new(zstd::Decoder::with_buffer(reader)?)))
+    } else if matches!(ext, "gz" | "zst") && !head.is_empty() {
+        anyhow::bail!("{}: .{ext} file without a {ext} header", path.display());
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+/// BLAKE3 of the raw (still compressed) input: the file's digest, or for
+/// several shards the digest of their digests in order.
+pub fn blake3(files: &[PathBuf]) -> anyhow::Result<String> {
+    let digest = |path: &Path| -> anyhow::Result<blake3::Hash> {
+        let file = fs::File::open(path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        Ok(blake3::Hasher::new().update_reader(file)?.finalize())
+    };
+    if let [file] = files {
+        return Ok(digest(file)?.to_hex().to_string());
+    }
+    let mut hasher = blake3::Hasher::new();
+    for file in files {
+        hasher.update(digest(file)?.as_bytes());
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+// `*` matches any run of characters, `?` exactly one.
+fn matches(pattern: &str, name: &str) -> bool {
+    let (p, n): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut pi, mut ni) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while ni < n.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == n[ni]) {
+            pi += 1;
+            ni += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ni));
+            pi += 1;
+        } else if let Some((sp, sn)) = star {
+            pi = sp + 1;
+            ni = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_run() {
+        assert!(matches("*", "pools.json"));
+        assert!(matches("*", ""));
+        assert!(matches("2024-*.json.zst", "2024-01.json.zst"));
+        assert!(matches("2024-*.json.zst", "2024-.json.zst"));
+        assert!(matches("*-*.json", "a-b-c.json"));
+        assert!(!matches("2024-*.json.zst", "2024-01.json"));
+        assert!(!matches("2024-*.json.zst", "2023-01.json.zst"));
+    }
+
+    #[test]
+    fn question_mark_matches_one() {
+        assert!(matches("part-??.json", "part-07.json"));
+        assert!(!matches("part-??.json", "part-7.json"));
+        assert!(!matches("part-??.json", "part-107.json"));
+        assert!(matches("p?rt.json", "pärt.json"));
+    }
+
+    #[test]
+    fn star_backtracks() {
+        assert!(matches("*.json", "a.json.json"));
+        assert!(matches("*a*b", "xaxxab"));
+        assert!(!matches("*a*b", "xaxxa"));
+        assert!(matches("a**", "a"));
+    }
+}
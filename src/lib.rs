@@ -0,0 +1,37 @@
+pub mod batch;
+pub mod build;
+pub mod bytes;
+pub mod cache;
+pub mod codegen;
+pub mod dedup;
+pub mod dense;
+pub mod diff;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+pub mod error;
+pub mod guard;
+pub mod handle;
+pub mod hexkey;
+pub mod input;
+pub mod manifest;
+pub mod merge;
+pub mod normalize;
+pub mod query;
+pub mod report;
+pub mod reverse;
+pub mod snapshot;
+pub mod sort;
+pub mod sources;
+#[cfg(test)]
+mod testutil;
+pub mod topic_map;
+pub mod utils;
+pub mod validity;
+pub mod verify;
+pub mod writer;
+
+pub use handle::{Reload, TopicMapHandle};
+pub use topic_map::TopicMap;
+
+/// A key and its topic ID, as inserted into the FST.
+pub type Pair = (Vec<u8>, u64);
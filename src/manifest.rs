@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, fs, path::Path};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::normalize::Normalize;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Major schema version written by this builder. Readers refuse anything newer.
+pub const MANIFEST_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub version: u32,
+    /// Unix seconds. `None` for manifests migrated from v1.
+    pub built_at: Option<u64>,
+    pub builder_version: String,
+    pub sources: BTreeMap<String, SourceEntry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dense: Option<DenseEntry>,
+    /// Extra reverse-index IDs that resolve to a key mapped to another ID.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aliases: Option<AliasEntry>,
+    /// Variant spellings added to the map as extra keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variants: Option<VariantEntry>,
+    /// Listing intervals in `validity::VALIDITY_FILE`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validity: Option<ValidityEntry>,
+    #[serde(default)]
+    pub files: Vec<FileEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceEntry {
+    pub parser: String,
+    pub count: u64,
+    /// Half-open range of IDs reserved for this source: `id_start..id_end`.
+    pub id_start: u64,
+    pub id_end: u64,
+    /// BLAKE3 of the input file. `None` for manifests migrated from v1.
+    pub input_blake3: Option<String>,
+    /// Rules keys were normalized with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normalize: Option<Normalize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DenseEntry {
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AliasEntry {
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariantEntry {
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ValidityEntry {
+    /// Keys with any interval, including delisted ones.
+    pub keys: u64,
+    pub intervals: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Path relative to the snapshot directory.
+    pub name: String,
+    pub size: u64,
+    pub blake3: String,
+}
+
+impl FileEntry {
+    pub fn from_file(dir: &Path, name: &str) -> anyhow::Result<Self> {
+        let bytes = fs::read(dir.join(name))?;
+        Ok(Self {
+            name: name.to_string(),
+            size: bytes.len() as u64,
+            blake3: blake3::hash(&bytes).to_hex().to_string(),
+        })
+    }
+}
+
+impl Manifest {
+    pub fn new(built_at: u64) -> Self {
+        Self {
+            version: MANIFEST_VERSION,
+            built_at: Some(built_at),
+            builder_version: env!("CARGO_PKG_VERSION").to_string(),
+            sources: BTreeMap::new(),
+            dense: None,
+            aliases: None,
+            variants: None,
+            validity: None,
+            files: vec![],
+        }
+    }
+
+    pub fn read(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let data = fs::read_to_string(&path)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        Self::from_json(&data).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+    }
+
+    /// Parses any supported schema version, upgrading older ones in memory.
+    pub fn from_json(data: &str) -> anyhow::Result<Self> {
+        let json: Value = serde_json::from_str(data)?;
+        let version = json.get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow::anyhow!("manifest has no numeric \"version\""))?;
+        match version {
+            1 => migrate_v1(&json),
+            2 => Ok(serde_json::from_value(json)?),
+            v => anyhow::bail!(
+                "unsupported manifest version {v} (this reader supports up to {MANIFEST_VERSION})"
+            ),
+        }
+    }
+
+    pub fn write(&self, dir: &Path) -> anyhow::Result<()> {
+        fs::write(dir.join(MANIFEST_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn file(&self, name: &str) -> Option<&FileEntry> {
+        self.files.iter().find(|f| f.name == name)
+    }
+
+    /// Name of the source whose ID range contains `id`.
+    pub fn source_of(&self, id: u64) -> Option<&str> {
+        self.sources.iter()
+            .find(|(_, s)| (s.id_start..s.id_end).contains(&id))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+// v1 was `{"<source>": {"count": n}, ..., "version": 1}` written by the
+// fixed two-source builder, so source ranges are those of that layout.
+fn migrate_v1(json: &Value) -> anyhow::Result<Manifest> {
+    let obj = json.as_object()
+        .ok_or_else(|| anyhow::anyhow!("Expected JSON object"))?;
+
+    let mut manifest = Manifest {
+        version: MANIFEST_VERSION,
+        built_at: None,
+        builder_version: String::new(),
+        sources: BTreeMap::new(),
+        dense: None,
+        aliases: None,
+        variants: None,
+        validity: None,
+        files: vec![],
+    };
+
+    for (key, val) in obj {
+        if key == "version" {
+            continue;
+        }
+        let count = val.get("count")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| anyhow::anyhow!("v1 entry {key:?} has no numeric \"count\""))?;
+        let id_start = match key.as_str() {
+            "dense" => {
+                manifest.dense = Some(DenseEntry { count });
+                continue;
+            }
+            "binance" => 0,
+            "uniswap" => 10_000,
+            other => anyhow::bail!("v1 manifest has unknown source {other:?}"),
+        };
+        manifest.sources.insert(key.clone(), SourceEntry {
+            parser: key.clone(),
+            count,
+            id_start,
+            id_end: id_start + count,
+            input_blake3: None,
+            normalize: None,
+        });
+    }
+
+    Ok(manifest)
+}
@@ -0,0 +1,282 @@
+use std::{str::FromStr, time::SystemTime};
+use fst::{map::{IndexedValue, OpBuilder}, Streamer};
+use crate::manifest::{Manifest, SourceEntry};
+use crate::normalize::Variant;
+use crate::validity::History;
+use crate::{utils, Pair, TopicMap};
+
+/// What to do when the same key maps to different IDs in two inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Fail the merge, listing the conflicting keys.
+    Error,
+    /// Keep the ID from the input listed first.
+    PreferLeft,
+    /// Keep the ID from the input with the latest `built_at` (later input on ties).
+    PreferNewest,
+}
+
+impl FromStr for ConflictPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "error" => Ok(ConflictPolicy::Error),
+            "prefer-left" => Ok(ConflictPolicy::PreferLeft),
+            "prefer-newest" => Ok(ConflictPolicy::PreferNewest),
+            other => anyhow::bail!("unknown conflict policy {other:?} (error, prefer-left, prefer-newest)"),
+        }
+    }
+}
+
+pub struct Merged {
+    /// Sorted by key, ready for `writer::write_snapshot`. The inputs' aliases
+    /// follow their key as repeats.
+    pub pairs: Vec<Pair>,
+    pub manifest: Manifest,
+    /// Variant spellings of the inputs, re-added for their key's merged ID.
+    pub variants: Vec<Variant>,
+    /// (key, IDs per input) for every key resolved by the policy.
+    pub conflicts: Vec<(Vec<u8>, Vec<Option<u64>>)>,
+    /// The inputs' validity intervals, if any input has a table.
+    pub history: Option<History>,
+}
+
+/// Unions the inputs' FSTs, aliases, manifests and validity tables. Sources
+/// with the same name are taken from the preferred input; sources with
+/// different names must not have overlapping ID ranges. An alias is kept
+/// unless it is its key's merged ID.
+pub fn merge(inputs: &[TopicMap], policy: ConflictPolicy) -> anyhow::Result<Merged> {
+    if inputs.is_empty() {
+        anyhow::bail!("nothing to merge");
+    }
+
+    // Input indexes, most preferred first
+    let mut preference: Vec<usize> = (0..inputs.len()).collect();
+    if policy == ConflictPolicy::PreferNewest {
+        preference.sort_by_key(|&i| std::cmp::Reverse((inputs[i].manifest().built_at, i)));
+    }
+
+    let mut op = OpBuilder::new();
+    for input in inputs {
+        op = op.add(input.as_fst());
+    }
+    let mut union = op.union();
+
+    let mut pairs = vec![];
+    let mut variants = vec![];
+    let mut conflicts = vec![];
+    while let Some((key, values)) = union.next() {
+        // Variant spellings aren't keys of their own: they follow their key
+        let (values, spellings): (Vec<&IndexedValue>, Vec<_>) = values.iter()
+            .partition(|v| variant_of(&inputs[v.index], key, v.value).is_none());
+        if values.is_empty() {
+            let preferred = preference.iter()
+                .find_map(|&i| spellings.iter().find(|v| v.index == i))
+                .unwrap();
+            let of = variant_of(&inputs[preferred.index], key, preferred.value).unwrap();
+            variants.push((key.to_vec(), of));
+            continue;
+        }
+        let first = values[0].value;
+        if values.iter().all(|v| v.value == first) {
+            pairs.push((key.to_vec(), first));
+            continue;
+        }
+        let mut ids = vec![None; inputs.len()];
+        for v in values {
+            ids[v.index] = Some(v.value);
+        }
+        let winner = preference.iter().find_map(|&i| ids[i]).unwrap();
+        pairs.push((key.to_vec(), winner));
+        conflicts.push((key.to_vec(), ids));
+    }
+
+    if policy == ConflictPolicy::Error && !conflicts.is_empty() {
+        let sample: Vec<String> = conflicts.iter().take(10)
+            .map(|(k, ids)| format!("{} {ids:?}", utils::display_key(k)))
+            .collect();
+        anyhow::bail!(
+            "{} keys map to different IDs across inputs:\n  {}",
+            conflicts.len(),
+            sample.join("\n  ")
+        );
+    }
+
+    let manifest = merge_manifests(inputs, &preference, policy, &pairs)?;
+
+    let mut aliases: Vec<Pair> = inputs.iter().flat_map(TopicMap::aliases).collect();
+    aliases.sort_unstable();
+    aliases.dedup();
+    aliases.retain(|(key, id)| {
+        pairs.binary_search_by(|(k, _)| k.as_slice().cmp(key))
+            .is_ok_and(|i| pairs[i].1 != *id)
+    });
+
+    // Two keys sharing an ID would silently alias topics downstream
+    let mut ids: Vec<(u64, &[u8])> = pairs.iter().chain(&aliases).map(|(k, id)| (*id, k.as_slice())).collect();
+    ids.sort_unstable();
+    if let Some(w) = ids.windows(2).find(|w| w[0].0 == w[1].0) {
+        anyhow::bail!(
+            "ID {} is used by both {} and {} after merging",
+            w[0].0, utils::display_key(w[0].1), utils::display_key(w[1].1)
+        );
+    }
+
+    // Stable, so each key's merged ID stays first
+    pairs.append(&mut aliases);
+    pairs.sort_by(|a, b| a.0.cmp(&b.0));
+    let history = History::merged(preference.iter().map(|&i| &inputs[i]));
+    Ok(Merged { pairs, manifest, variants, conflicts, history })
+}
+
+// The key `key` is a variant spelling of in `input`, if it is one
+fn variant_of(input: &TopicMap, key: &[u8], id: u64) -> Option<Vec<u8>> {
+    input.manifest().variants.as_ref()?;
+    input.key_of(id).filter(|of| of != key)
+}
+
+fn merge_manifests(
+    inputs: &[TopicMap],
+    preference: &[usize],
+    policy: ConflictPolicy,
+    pairs: &[Pair],
+) -> anyhow::Result<Manifest> {
+    let built_at = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    let mut manifest = Manifest::new(built_at);
+
+    // Partial builds carry empty entries for sources they don't own; those
+    // only fill in names no other input provides.
+    let entries = |non_empty: bool| preference.iter()
+        .flat_map(|&i| &inputs[i].manifest().sources)
+        .filter(move |(_, e)| (e.count > 0) == non_empty);
+    for (name, entry) in entries(true).chain(entries(false)) {
+        match manifest.sources.get(name) {
+            None => {
+                manifest.sources.insert(name.clone(), entry.clone());
+            }
+            Some(kept) if policy == ConflictPolicy::Error
+                && entry.count > 0
+                && (kept.id_start, kept.id_end) != (entry.id_start, entry.id_end) =>
+            {
+                anyhow::bail!(
+                    "source {name:?} has ID range {}..{} in one input and {}..{} in another",
+                    kept.id_start, kept.id_end, entry.id_start, entry.id_end
+                );
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut ranges: Vec<(&String, &SourceEntry)> = manifest.sources.iter().collect();
+    ranges.sort_by_key(|(_, s)| s.id_start);
+    for w in ranges.windows(2) {
+        if w[0].1.id_end > w[1].1.id_start {
+            anyhow::bail!(
+                "sources {:?} ({}..{}) and {:?} ({}..{}) have overlapping ID ranges",
+                w[0].0, w[0].1.id_start, w[0].1.id_end,
+                w[1].0, w[1].1.id_start, w[1].1.id_end
+            );
+        }
+    }
+
+    // Counts describe the merged map, not any one input
+    for entry in manifest.sources.values_mut() {
+        entry.count = pairs.iter()
+            .filter(|(_, id)| (entry.id_start..entry.id_end).contains(id))
+            .count() as u64;
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil, writer};
+
+    // binance 0..10, uniswap 10..20
+    fn input(root: &std::path::Path, built_at: u64, keys: &[(&str, u64)]) -> TopicMap {
+        let manifest = testutil::manifest(built_at, &[("binance", 0, 10), ("uniswap", 10, 20)]);
+        testutil::snapshot(root, manifest, keys, None)
+    }
+
+    fn ids(merged: &Merged) -> Vec<(&str, u64)> {
+        merged.pairs.iter().map(|(k, id)| (std::str::from_utf8(k).unwrap(), *id)).collect()
+    }
+
+    #[test]
+    fn conflicts_follow_the_policy() {
+        let root = testutil::root("merge-policy");
+        let left = input(&root, 2, &[("A", 0), ("B", 1)]);
+        let right = input(&root, 1, &[("A", 0), ("B", 2), ("C", 10)]);
+        let inputs = [left, right];
+
+        let e = merge(&inputs, ConflictPolicy::Error).err().unwrap();
+        assert!(e.to_string().contains("1 keys map to different IDs"), "{e}");
+
+        let merged = merge(&inputs, ConflictPolicy::PreferLeft).unwrap();
+        assert_eq!(ids(&merged), vec![("A", 0), ("B", 1), ("C", 10)]);
+        assert_eq!(merged.conflicts, vec![(b"B".to_vec(), vec![Some(1), Some(2)])]);
+        assert_eq!((merged.manifest.sources["binance"].count, merged.manifest.sources["uniswap"].count), (2, 1));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn prefer_newest_ignores_order() {
+        let root = testutil::root("merge-newest");
+        let old = input(&root, 1, &[("B", 1)]);
+        let new = input(&root, 2, &[("B", 2)]);
+        let merged = merge(&[old, new], ConflictPolicy::PreferNewest).unwrap();
+        assert_eq!(ids(&merged), vec![("B", 2)]);
+        let [old, new] = [input(&root, 1, &[("B", 1)]), input(&root, 2, &[("B", 2)])];
+        let merged = merge(&[new, old], ConflictPolicy::PreferNewest).unwrap();
+        assert_eq!(ids(&merged), vec![("B", 2)]);
+
+        // Ties go to the later input
+        let [first, second] = [input(&root, 1, &[("B", 1)]), input(&root, 1, &[("B", 2)])];
+        let merged = merge(&[first, second], ConflictPolicy::PreferNewest).unwrap();
+        assert_eq!(ids(&merged), vec![("B", 2)]);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn rejects_shared_ids_and_overlapping_ranges() {
+        let root = testutil::root("merge-checks");
+        let left = input(&root, 1, &[("A", 0)]);
+        let right = input(&root, 1, &[("C", 0)]);
+        let e = merge(&[left, right], ConflictPolicy::PreferLeft).err().unwrap();
+        assert!(e.to_string().contains("ID 0 is used by both A and C"), "{e}");
+
+        let left = input(&root, 1, &[("A", 0)]);
+        let manifest = testutil::manifest(1, &[("kraken", 5, 15)]);
+        let right = testutil::snapshot(&root, manifest, &[("K", 5)], None);
+        let e = merge(&[left, right], ConflictPolicy::PreferLeft).err().unwrap();
+        assert!(e.to_string().contains("overlapping ID ranges"), "{e}");
+
+        let left = input(&root, 1, &[("A", 0)]);
+        let manifest = testutil::manifest(1, &[("binance", 0, 5)]);
+        let right = testutil::snapshot(&root, manifest, &[("Z", 4)], None);
+        let e = merge(&[left, right], ConflictPolicy::Error).err().unwrap();
+        assert!(e.to_string().contains("has ID range 0..10 in one input and 0..5 in another"), "{e}");
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn aliases_are_carried() {
+        let root = testutil::root("merge-aliases");
+        // B's uniswap ID 11 is an alias of its binance ID
+        let left = input(&root, 1, &[("A", 0), ("B", 1), ("B", 11)]);
+        let right = input(&root, 1, &[("B", 1), ("C", 12)]);
+        assert_eq!(left.aliases(), vec![(b"B".to_vec(), 11)]);
+
+        let mut merged = merge(&[left, right], ConflictPolicy::Error).unwrap();
+        assert_eq!(ids(&merged), vec![("A", 0), ("B", 1), ("B", 11), ("C", 12)]);
+        let out = root.join("out");
+        let written = writer::write_snapshot(&out, &mut merged.manifest, merged.pairs.into_iter().map(Ok), vec![], None, false).unwrap();
+        let map = TopicMap::open(&written.dir).unwrap();
+        assert_eq!(map.get("B"), Some(1));
+        assert_eq!(map.key_of(11).as_deref(), Some(&b"B"[..]));
+        assert_eq!(map.manifest().aliases.as_ref().map(|a| a.count), Some(1));
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
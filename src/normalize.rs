@@ -0,0 +1,140 @@
+use std::{fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+
+/// (variant spelling, normalized key).
+pub type Variant = (Vec<u8>, Vec<u8>);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Case {
+    Upper,
+    Lower,
+}
+
+/// Per-source key rules, e.g. `upper,strip=-/_` turns `eth-usdt` into
+/// `ETHUSDT`. Applied to keys at build time and recorded in the manifest, so
+/// readers can apply the same rules to lookups (`TopicMap::normalize_and_get`).
+/// Case folding is ASCII only.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Normalize {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub case: Option<Case>,
+    /// Separator characters removed from keys.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub strip: String,
+    /// Also add input spellings that differ from the normalized key, and the
+    /// normalized key in the other case, to the map as extra keys for its ID.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub variants: bool,
+}
+
+impl Normalize {
+    pub fn apply(&self, key: &[u8]) -> Vec<u8> {
+        key.iter()
+            .filter(|b| !self.strip.as_bytes().contains(b))
+            .map(|&b| match self.case {
+                Some(Case::Upper) => b.to_ascii_uppercase(),
+                Some(Case::Lower) => b.to_ascii_lowercase(),
+                None => b,
+            })
+            .collect()
+    }
+
+    /// Spellings of `key` to map to `normalized` when `variants` is set.
+    pub fn variants(&self, key: &[u8], normalized: &[u8]) -> Vec<Vec<u8>> {
+        if !self.variants {
+            return vec![];
+        }
+        let mut out = vec![];
+        if key != normalized {
+            out.push(key.to_vec());
+        }
+        let flipped = match self.case {
+            Some(Case::Upper) => normalized.to_ascii_lowercase(),
+            Some(Case::Lower) => normalized.to_ascii_uppercase(),
+            None => return out,
+        };
+        if flipped != normalized && flipped != key {
+            out.push(flipped);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Normalize {
+    /// The rules as `FromStr` reads them, e.g. `upper,strip=-/_`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut rules = vec![];
+        match self.case {
+            Some(Case::Upper) => rules.push("upper".to_string()),
+            Some(Case::Lower) => rules.push("lower".to_string()),
+            None => {}
+        }
+        if !self.strip.is_empty() {
+            rules.push(format!("strip={}", self.strip));
+        }
+        if self.variants {
+            rules.push("variants".to_string());
+        }
+        f.write_str(&rules.join(","))
+    }
+}
+
+impl FromStr for Normalize {
+    type Err = anyhow::Error;
+
+    /// Comma-separated `upper`, `lower`, `strip=<chars>` and `variants`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut rules = Normalize::default();
+        for rule in s.split(',') {
+            match rule {
+                "upper" | "lower" if rules.case.is_some() => anyhow::bail!("{s:?}: more than one case rule"),
+                "upper" => rules.case = Some(Case::Upper),
+                "lower" => rules.case = Some(Case::Lower),
+                "variants" => rules.variants = true,
+                _ => match rule.strip_prefix("strip=") {
+                    Some(chars) if !chars.is_empty() && chars.is_ascii() => rules.strip.push_str(chars),
+                    Some(_) => anyhow::bail!("{s:?}: strip= needs ASCII separator characters"),
+                    None => anyhow::bail!(
+                        "unknown normalization rule {rule:?} (upper, lower, strip=<chars>, variants)"
+                    ),
+                },
+            }
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_case_and_strip() {
+        let rules: Normalize = "upper,strip=-/_".parse().unwrap();
+        assert_eq!(rules.apply(b"eth-usdt"), b"ETHUSDT");
+        assert_eq!(rules.apply(b"Btc/Usd_T"), b"BTCUSDT");
+        assert_eq!(rules.apply(b"-/"), b"");
+        assert_eq!("lower".parse::<Normalize>().unwrap().apply(b"ETHUSDT"), b"ethusdt");
+    }
+
+    #[test]
+    fn displays_as_parsed() {
+        for rules in ["upper,strip=-/_", "lower", "strip=-", "upper,variants"] {
+            assert_eq!(rules.parse::<Normalize>().unwrap().to_string(), rules);
+        }
+        assert!("upper,lower".parse::<Normalize>().is_err());
+        assert!("strip=".parse::<Normalize>().is_err());
+        assert!("aliases".parse::<Normalize>().is_err());
+    }
+
+    #[test]
+    fn variants_are_other_spellings() {
+        let rules: Normalize = "upper,strip=-,variants".parse().unwrap();
+        assert_eq!(rules.variants(b"eth-usdt", b"ETHUSDT"), [b"eth-usdt".to_vec(), b"ethusdt".to_vec()]);
+        assert_eq!(rules.variants(b"ethusdt", b"ETHUSDT"), [b"ethusdt".to_vec()]);
+        assert_eq!(rules.variants(b"ETHUSDT", b"ETHUSDT"), [b"ethusdt".to_vec()]);
+        let off: Normalize = "upper,strip=-".parse().unwrap();
+        assert!(off.variants(b"eth-usdt", b"ETHUSDT").is_empty());
+    }
+}
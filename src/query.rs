@@ -0,0 +1,214 @@
+use fst::{automaton::{Automaton, Levenshtein, Str}, IntoStreamer, Streamer};
+use regex_automata::dense;
+use crate::TopicMap;
+
+/// Key-space queries over the topic map, streamed straight from the FST.
+#[derive(Debug, Clone)]
+pub enum Query {
+    Exact(Vec<u8>),
+    /// Keys starting with the given bytes (`ETH*`).
+    Prefix(Vec<u8>),
+    /// Keys in the half-open lexicographic range `start..end`; open ends allowed.
+    Range { start: Option<Vec<u8>>, end: Option<Vec<u8>> },
+    /// Keys fully matching an anchored regex (`ETH.*USDT`).
+    Regex(String),
+}
+
+impl Query {
+    /// CLI syntax: `ETH*` is a prefix, `A..B` a range (either side may be
+    /// empty), `/re/` a regex, anything else an exact key.
+    pub fn parse(s: &str) -> Self {
+        if let Some(re) = s.strip_prefix('/').and_then(|r| r.strip_suffix('/')) {
+            Query::Regex(re.to_string())
+        } else if let Some(prefix) = s.strip_suffix('*') {
+            Query::Prefix(prefix.as_bytes().to_vec())
+        } else if let Some((start, end)) = s.split_once("..") {
+            let bound = |b: &str| (!b.is_empty()).then(|| b.as_bytes().to_vec());
+            Query::Range { start: bound(start), end: bound(end) }
+        } else {
+            Query::Exact(s.as_bytes().to_vec())
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Hit {
+    pub key: Vec<u8>,
+    pub id: u64,
+    /// Source whose ID range contains `id`, per the manifest.
+    pub source: Option<String>,
+}
+
+impl TopicMap {
+    /// Runs `query`, keeping only keys of `source` if given, in key order.
+    pub fn search(&self, query: &Query, source: Option<&str>, limit: Option<usize>) -> anyhow::Result<Vec<Hit>> {
+        let range = self.source_range(source)?;
+        let limit = limit.unwrap_or(usize::MAX);
+        let fst = self.as_fst();
+
+        match query {
+            Query::Exact(key) => Ok(fst.get(key)
+                .filter(|id| range.as_ref().is_none_or(|r| r.contains(id)))
+                .map(|id| self.hit(key, id))
+                .into_iter()
+                .take(limit)
+                .collect()),
+            Query::Prefix(prefix) => {
+                let prefix = std::str::from_utf8(prefix)?;
+                let automaton = Str::new(prefix).starts_with();
+                Ok(self.collect(fst.search(automaton).into_stream(), range, limit))
+            }
+            Query::Range { start, end } => {
+                let mut builder = fst.range();
+                if let Some(start) = start {
+                    builder = builder.ge(start);
+                }
+                if let Some(end) = end {
+                    builder = builder.lt(end);
+                }
+                Ok(self.collect(builder.into_stream(), range, limit))
+            }
+            Query::Regex(pattern) => {
+                // Longest match, so the FST sees every full match of a key:
+                // leftmost-first stops `ETHB|ETHBTC` at `ETHB` for `ETHBTC`
+                let dfa = dense::Builder::new().anchored(true).longest_match(true).build(pattern)
+                    .map_err(|e| anyhow::anyhow!("regex {pattern:?}: {e}"))?;
+                Ok(self.collect(fst.search(&dfa).into_stream(), range, limit))
+            }
+        }
+    }
+
+    /// Keys within edit distance `max_distance` of `key`, nearest first
+    /// (ties in key order). Used for "did you mean" suggestions.
+    pub fn fuzzy(&self, key: &str, max_distance: u32, source: Option<&str>, limit: Option<usize>) -> anyhow::Result<Vec<(u32, Hit)>> {
+        let automaton = Levenshtein::new(key, max_distance)
+            .map_err(|e| anyhow::anyhow!("fuzzy {key:?}: {e}"))?;
+        let range = self.source_range(source)?;
+        let hits = self.collect(self.as_fst().search(automaton).into_stream(), range, usize::MAX);
+
+        let mut ranked: Vec<(u32, Hit)> = hits
+            .into_iter()
+            .map(|hit| (edit_distance(key.as_bytes(), &hit.key), hit))
+            .collect();
+        ranked.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.key.cmp(&b.1.key)));
+        ranked.truncate(limit.unwrap_or(usize::MAX));
+        Ok(ranked)
+    }
+
+    fn source_range(&self, source: Option<&str>) -> anyhow::Result<Option<std::ops::Range<u64>>> {
+        match source {
+            Some(name) => {
+                let s = self.manifest().sources.get(name)
+                    .ok_or_else(|| anyhow::anyhow!("unknown source {name:?}"))?;
+                Ok(Some(s.id_start..s.id_end))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn collect<S>(&self, mut stream: S, range: Option<std::ops::Range<u64>>, limit: usize) -> Vec<Hit>
+    where
+        S: for<'a> Streamer<'a, Item = (&'a [u8], u64)>,
+    {
+        let mut hits = vec![];
+        while let Some((key, id)) = stream.next() {
+            if hits.len() >= limit {
+                break;
+            }
+            if range.as_ref().is_some_and(|r| !r.contains(&id)) {
+                continue;
+            }
+            hits.push(self.hit(key, id));
+        }
+        hits
+    }
+
+    fn hit(&self, key: &[u8], id: u64) -> Hit {
+        Hit {
+            key: key.to_vec(),
+            id,
+            source: self.manifest().source_of(id).map(str::to_string),
+        }
+    }
+}
+
+// Plain Levenshtein distance over bytes (keys are ASCII symbols).
+fn edit_distance(a: &[u8], b: &[u8]) -> u32 {
+    let mut prev: Vec<u32> = (0..=b.len() as u32).collect();
+    let mut cur = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        cur[0] = i as u32 + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let subst = prev[j] + u32::from(ca != cb);
+            cur[j + 1] = subst.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    fn map(name: &str) -> TopicMap {
+        let root = testutil::root(name);
+        let manifest = testutil::manifest(100, &[("binance", 0, 100), ("uniswap", 100, 200)]);
+        let keys = [("ETH", 1), ("ETHBTC", 2), ("ETHUSDT", 3), ("BTCUSDT", 4), ("ETHX", 101), ("SOLUSDT", 5)];
+        testutil::snapshot(&root, manifest, &keys, None)
+    }
+
+    fn keys(map: &TopicMap, query: &str, source: Option<&str>, limit: Option<usize>) -> Vec<String> {
+        map.search(&Query::parse(query), source, limit).unwrap().into_iter()
+            .map(|hit| String::from_utf8(hit.key).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parses_cli_syntax() {
+        assert!(matches!(Query::parse("/E.*/"), Query::Regex(re) if re == "E.*"));
+        assert!(matches!(Query::parse("ETH*"), Query::Prefix(p) if p == b"ETH"));
+        assert!(matches!(Query::parse("A..B"), Query::Range { start: Some(_), end: Some(_) }));
+        assert!(matches!(Query::parse("..B"), Query::Range { start: None, end: Some(_) }));
+        assert!(matches!(Query::parse("ETH"), Query::Exact(k) if k == b"ETH"));
+    }
+
+    #[test]
+    fn prefix() {
+        let map = map("query-prefix");
+        assert_eq!(keys(&map, "ETH*", None, None), ["ETH", "ETHBTC", "ETHUSDT", "ETHX"]);
+        assert_eq!(keys(&map, "ETH*", None, Some(2)), ["ETH", "ETHBTC"]);
+        assert!(keys(&map, "XRP*", None, None).is_empty());
+    }
+
+    #[test]
+    fn range_is_half_open() {
+        let map = map("query-range");
+        assert_eq!(keys(&map, "ETH..ETHUSDT", None, None), ["ETH", "ETHBTC"]);
+        assert_eq!(keys(&map, "S..", None, None), ["SOLUSDT"]);
+        assert_eq!(keys(&map, "..ETH", None, None), ["BTCUSDT"]);
+    }
+
+    #[test]
+    fn regex_matches_whole_keys() {
+        let map = map("query-regex");
+        assert_eq!(keys(&map, "/ETH.*USDT/", None, None), ["ETHUSDT"]);
+        assert_eq!(keys(&map, "/.*USDT/", None, None), ["BTCUSDT", "ETHUSDT", "SOLUSDT"]);
+        assert_eq!(keys(&map, "/ETH/", None, None), ["ETH"]);
+        // A branch that is a prefix of another doesn't hide the longer key
+        assert_eq!(keys(&map, "/ETHB|ETHBTC/", None, None), ["ETHBTC"]);
+        assert_eq!(keys(&map, "/ETHBTC|ETH/", None, None), ["ETH", "ETHBTC"]);
+        assert!(map.search(&Query::parse("/(/"), None, None).is_err());
+    }
+
+    #[test]
+    fn source_scopes_results() {
+        let map = map("query-source");
+        assert_eq!(keys(&map, "ETH*", Some("uniswap"), None), ["ETHX"]);
+        assert_eq!(keys(&map, "ETH*", Some("binance"), None), ["ETH", "ETHBTC", "ETHUSDT"]);
+        assert!(keys(&map, "ETHX", Some("binance"), None).is_empty());
+        assert_eq!(keys(&map, "ETHX", Some("uniswap"), None), ["ETHX"]);
+        assert!(map.search(&Query::parse("ETH*"), Some("kraken"), None).is_err());
+    }
+}
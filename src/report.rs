@@ -0,0 +1,157 @@
+use std::{collections::BTreeMap, fs, path::Path, time::{Duration, Instant}};
+use serde::Serialize;
+use crate::error::BuildError;
+use crate::manifest::Manifest;
+
+// Written into each snapshot directory after it is published, for pipelines
+// to alert on. Not listed in the manifest: it describes the build, not the data.
+pub const REPORT_FILE: &str = "build-report.json";
+
+/// Rejected records kept per source; the rest are only counted.
+pub const MAX_SAMPLES: usize = 20;
+
+#[derive(Debug, Serialize)]
+pub struct BuildReport {
+    pub snapshot: String,
+    pub built_at: Option<u64>,
+    pub entries: u64,
+    pub sources: BTreeMap<String, SourceReport>,
+    /// Wall time per phase, in run order.
+    pub phases: Vec<Phase>,
+    pub outputs: Vec<Output>,
+}
+
+/// Key counts for one source: `parsed` records split into `accepted` keys,
+/// `rejected` malformed records, `duplicates` of an earlier key in the same
+/// source and keys `filtered` out by source rules. `cross_source_duplicates`
+/// are keys another source already had, `aliased` of which kept their ID.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceReport {
+    pub files: usize,
+    /// Keys came from the parse cache; only `accepted` is known.
+    pub cached: bool,
+    pub parsed: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    pub duplicates: u64,
+    pub filtered: u64,
+    pub cross_source_duplicates: u64,
+    pub aliased: u64,
+    pub rejected_samples: Vec<RejectedSample>,
+    pub duplicate_samples: Vec<DuplicateSample>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateSample {
+    pub key: String,
+    /// Source that kept the key; this source for within-source duplicates.
+    pub kept_in: String,
+    /// `file[:line:column (pointer)]` of the duplicate, when known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedSample {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub pointer: String,
+    pub value: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Phase {
+    pub name: &'static str,
+    pub seconds: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Output {
+    pub name: String,
+    pub size: u64,
+}
+
+impl Phase {
+    pub fn new(name: &'static str, elapsed: Duration) -> Self {
+        Self { name, seconds: elapsed.as_secs_f64() }
+    }
+
+    /// Runs `f` and records how long it took.
+    pub fn time<T>(phases: &mut Vec<Phase>, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let out = f();
+        phases.push(Phase::new(name, start.elapsed()));
+        out
+    }
+}
+
+impl SourceReport {
+    /// Adds a rejected record, sampling the first `MAX_SAMPLES`.
+    pub fn reject(&mut self, e: &BuildError) {
+        self.rejected += 1;
+        if let BuildError::InvalidRecord { path, at, value, reason, .. } = e {
+            if self.rejected_samples.len() < MAX_SAMPLES {
+                self.rejected_samples.push(RejectedSample {
+                    file: path.display().to_string(),
+                    line: at.line,
+                    column: at.column,
+                    pointer: at.pointer.clone(),
+                    value: value.clone(),
+                    reason: reason.clone(),
+                });
+            }
+        }
+    }
+}
+
+impl SourceReport {
+    /// Samples a duplicate; the caller counts it.
+    pub fn duplicate(&mut self, e: &BuildError) {
+        if let BuildError::Duplicate { key, kept_in, path, at, .. } = e {
+            if self.duplicate_samples.len() < MAX_SAMPLES {
+                let at = path.as_ref().map(|path| match at {
+                    Some(at) if at.pointer.is_empty() => format!("{}:{}:{}", path.display(), at.line, at.column),
+                    Some(at) => format!("{}:{}:{} ({})", path.display(), at.line, at.column, at.pointer),
+                    None => path.display().to_string(),
+                });
+                self.duplicate_samples.push(DuplicateSample { key: key.clone(), kept_in: kept_in.to_string(), at });
+            }
+        }
+    }
+}
+
+impl BuildReport {
+    /// Fills `accepted` and `outputs` from the written manifest.
+    pub fn new(
+        snapshot: &str,
+        entries: u64,
+        manifest: &Manifest,
+        mut sources: BTreeMap<String, SourceReport>,
+        phases: Vec<Phase>,
+    ) -> Self {
+        for (name, entry) in &manifest.sources {
+            sources.entry(name.clone()).or_default().accepted = entry.count;
+        }
+        Self {
+            snapshot: snapshot.to_string(),
+            built_at: manifest.built_at,
+            entries,
+            sources,
+            phases,
+            outputs: manifest.files.iter()
+                .map(|f| Output { name: f.name.clone(), size: f.size })
+                .collect(),
+        }
+    }
+
+    /// Writes the report into `dir` via a temporary file, so readers never see
+    /// a partial one.
+    pub fn write(&self, dir: &Path) -> anyhow::Result<()> {
+        let tmp = dir.join(format!("{REPORT_FILE}.tmp"));
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp, dir.join(REPORT_FILE))?;
+        Ok(())
+    }
+}
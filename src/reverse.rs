@@ -0,0 +1,119 @@
+use std::{fs, io::{BufWriter, Write}, path::Path};
+use fst::{Map, Streamer};
+use crate::bytes::{Bytes, LoadMode};
+use crate::Pair;
+
+// Topic ID -> key index, for reverse lookups without scanning the FST.
+// Layout (all little-endian):
+//   count: u64
+//   count x (id: u64, offset: u64)   sorted by id, offset into the key blob
+//   key blob                          key i spans offset[i]..offset[i + 1]
+pub const REVERSE_FILE: &str = "topic.rev.bin";
+
+const ENTRY: usize = 16;
+
+/// Builds the index from a written map plus alias (key, ID) pairs. Keys are
+/// streamed to a key-ordered scratch file first, so only the (id, offset,
+/// len) entries are held in memory.
+pub fn write<D: AsRef<[u8]>>(path: &Path, map: &Map<D>, aliases: &[Pair]) -> anyhow::Result<()> {
+    let scratch = path.with_extension("keys.tmp");
+    let mut keys = BufWriter::new(fs::File::create(&scratch)?);
+    let mut by_id: Vec<(u64, u64, u32)> = Vec::with_capacity(map.len());
+    let mut offset = 0u64;
+    let mut stream = map.stream();
+    while let Some((key, id)) = stream.next() {
+        keys.write_all(key)?;
+        by_id.push((id, offset, key.len() as u32));
+        offset += key.len() as u64;
+    }
+    for (key, id) in aliases {
+        keys.write_all(key)?;
+        by_id.push((*id, offset, key.len() as u32));
+        offset += key.len() as u64;
+    }
+    keys.flush()?;
+    drop(keys);
+    by_id.sort_unstable_by_key(|&(id, _, _)| id);
+
+    let keys = Bytes::load(&scratch, LoadMode::Mmap)?;
+    let keys = keys.as_ref();
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    out.write_all(&(by_id.len() as u64).to_le_bytes())?;
+    let mut blob_offset = 0u64;
+    for &(id, _, len) in &by_id {
+        out.write_all(&id.to_le_bytes())?;
+        out.write_all(&blob_offset.to_le_bytes())?;
+        blob_offset += len as u64;
+    }
+    for &(_, at, len) in &by_id {
+        out.write_all(&keys[at as usize..at as usize + len as usize])?;
+    }
+    out.flush()?;
+    fs::remove_file(&scratch)?;
+    Ok(())
+}
+
+pub struct ReverseIndex {
+    bytes: Bytes,
+    len: usize,
+}
+
+impl ReverseIndex {
+    pub fn open(path: &Path, mode: LoadMode) -> anyhow::Result<Self> {
+        let bytes = Bytes::load(path, mode)?;
+        let data = bytes.as_ref();
+        let len = data.get(..8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| anyhow::anyhow!("{}: truncated header", path.display()))?;
+        if data.len() < 8 + len.saturating_mul(ENTRY) {
+            anyhow::bail!("{}: truncated index for {len} entries", path.display());
+        }
+        Ok(Self { bytes, len })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Key for topic `id`, by binary search over the ID-sorted entries.
+    pub fn key(&self, id: u64) -> Option<&[u8]> {
+        let (mut lo, mut hi) = (0usize, self.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.entry(mid).0.cmp(&id) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return self.key_at(mid),
+            }
+        }
+        None
+    }
+
+    /// (ID, key) entries in ID order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &[u8])> + '_ {
+        (0..self.len).filter_map(|i| Some((self.entry(i).0, self.key_at(i)?)))
+    }
+
+    fn entry(&self, i: usize) -> (u64, u64) {
+        let at = 8 + i * ENTRY;
+        let data = self.bytes.as_ref();
+        (
+            u64::from_le_bytes(data[at..at + 8].try_into().unwrap()),
+            u64::from_le_bytes(data[at + 8..at + 16].try_into().unwrap()),
+        )
+    }
+
+    fn key_at(&self, i: usize) -> Option<&[u8]> {
+        let data = self.bytes.as_ref();
+        let blob = data.get(8 + self.len * ENTRY..)?;
+        let start = self.entry(i).1 as usize;
+        let end = if i + 1 < self.len { self.entry(i + 1).1 as usize } else { blob.len() };
+        blob.get(start..end)
+    }
+}
@@ -0,0 +1,206 @@
+use std::{fs, io, path::{Path, PathBuf}};
+
+// Snapshot root layout:
+//   <root>/000001/{manifest.json, topic.map.fst, ...}
+//   <root>/000002/...
+//   <root>/current -> 000002
+// Builds only ever write into a fresh numbered directory and then swap the
+// `current` symlink with a rename, so readers never see a half-written snapshot.
+pub const CURRENT: &str = "current";
+
+pub const DEFAULT_KEEP: usize = 5;
+
+/// Resolves `current` once, so a reader opening several files cannot straddle
+/// a switch. Directories without a `current` pointer are returned as is.
+pub fn resolve(dir: &Path) -> anyhow::Result<PathBuf> {
+    match fs::read_link(dir.join(CURRENT)) {
+        Ok(target) => Ok(dir.join(target)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(dir.to_path_buf()),
+        Err(e) => Err(anyhow::anyhow!("{}: {e}", dir.join(CURRENT).display())),
+    }
+}
+
+/// Name of the snapshot `current` points to, if any.
+pub fn current(root: &Path) -> anyhow::Result<Option<String>> {
+    match fs::read_link(root.join(CURRENT)) {
+        Ok(target) => Ok(Some(target.to_string_lossy().into_owned())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(anyhow::anyhow!("{}: {e}", root.join(CURRENT).display())),
+    }
+}
+
+/// Snapshot directory names under `root`, oldest first.
+pub fn list(root: &Path) -> anyhow::Result<Vec<String>> {
+    let mut seqs = vec![];
+    for entry in fs::read_dir(root)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        if let Some(seq) = entry.file_name().to_str().and_then(parse_seq) {
+            seqs.push(seq);
+        }
+    }
+    seqs.sort_unstable();
+    Ok(seqs.into_iter().map(format_seq).collect())
+}
+
+/// Creates the next numbered snapshot directory. `create_dir` fails if it
+/// already exists, so concurrent builders never share a directory.
+pub fn create_next(root: &Path) -> anyhow::Result<(String, PathBuf)> {
+    fs::create_dir_all(root)?;
+    let mut seq = list(root)?.last().and_then(|s| parse_seq(s)).unwrap_or(0);
+    loop {
+        seq += 1;
+        let name = format_seq(seq);
+        let dir = root.join(&name);
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok((name, dir)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Flushes every file in `dir` and the directory entry itself to disk.
+pub fn sync_dir(dir: &Path) -> anyhow::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            fs::File::open(entry.path())?.sync_all()?;
+        }
+    }
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+/// Atomically points `current` at `name`: symlink to a temp name, then rename.
+pub fn publish(root: &Path, name: &str) -> anyhow::Result<()> {
+    if !root.join(name).is_dir() {
+        anyhow::bail!("{}: no such snapshot", root.join(name).display());
+    }
+    let tmp = root.join(format!(".{CURRENT}.tmp-{}", std::process::id()));
+    let _ = fs::remove_file(&tmp);
+    std::os::unix::fs::symlink(name, &tmp)?;
+    fs::rename(&tmp, root.join(CURRENT))?;
+    fs::File::open(root)?.sync_all()?;
+    Ok(())
+}
+
+/// Removes the oldest snapshots beyond `keep`, never the one `current` points to.
+pub fn prune(root: &Path, keep: usize) -> anyhow::Result<Vec<String>> {
+    let current = current(root)?;
+    let snapshots = list(root)?;
+    let excess = snapshots.len().saturating_sub(keep);
+
+    let mut removed = vec![];
+    for name in snapshots.into_iter().take(excess) {
+        if current.as_deref() == Some(name.as_str()) {
+            continue;
+        }
+        fs::remove_dir_all(root.join(&name))?;
+        removed.push(name);
+    }
+    Ok(removed)
+}
+
+/// Snapshot to roll back to: the newest one older than `current`.
+pub fn previous(root: &Path) -> anyhow::Result<Option<String>> {
+    let current = current(root)?
+        .ok_or_else(|| anyhow::anyhow!("{}: no {CURRENT} pointer", root.display()))?;
+    let cur_seq = parse_seq(&current)
+        .ok_or_else(|| anyhow::anyhow!("{CURRENT} points to unexpected {current:?}"))?;
+    Ok(list(root)?
+        .into_iter()
+        .rev()
+        .find(|s| parse_seq(s).is_some_and(|seq| seq < cur_seq)))
+}
+
+fn parse_seq(name: &str) -> Option<u64> {
+    if name.is_empty() || !name.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    name.parse().ok()
+}
+
+fn format_seq(seq: u64) -> String {
+    format!("{seq:06}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+
+    // `n` empty numbered snapshots, `current` on the last
+    fn snapshots(name: &str, n: usize) -> PathBuf {
+        let root = testutil::root(name);
+        for _ in 0..n {
+            let (name, _) = create_next(&root).unwrap();
+            publish(&root, &name).unwrap();
+        }
+        root
+    }
+
+    #[test]
+    fn create_next_numbers_past_the_newest() {
+        let root = snapshots("snapshot-create", 2);
+        assert_eq!(list(&root).unwrap(), vec!["000001", "000002"]);
+
+        fs::create_dir(root.join("000007")).unwrap();
+        fs::create_dir(root.join(".cache")).unwrap();
+        fs::create_dir(root.join("12a")).unwrap();
+        let (name, dir) = create_next(&root).unwrap();
+        assert_eq!((name.as_str(), dir), ("000008", root.join("000008")));
+        assert_eq!(list(&root).unwrap(), vec!["000001", "000002", "000007", "000008"]);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn previous_is_the_newest_older_snapshot() {
+        let empty = testutil::root("snapshot-no-current");
+        assert!(previous(&empty).is_err());
+        let _ = fs::remove_dir_all(&empty);
+
+        let root = snapshots("snapshot-previous", 4);
+        assert_eq!(previous(&root).unwrap().as_deref(), Some("000003"));
+        fs::remove_dir(root.join("000003")).unwrap();
+        assert_eq!(previous(&root).unwrap().as_deref(), Some("000002"));
+
+        publish(&root, "000001").unwrap();
+        assert_eq!(previous(&root).unwrap(), None);
+        assert!(publish(&root, "000003").is_err());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn prune_keeps_the_newest_and_current() {
+        let root = snapshots("snapshot-prune", 6);
+        assert_eq!(prune(&root, 3).unwrap(), vec!["000001", "000002", "000003"]);
+        assert_eq!(list(&root).unwrap(), vec!["000004", "000005", "000006"]);
+        assert!(prune(&root, 3).unwrap().is_empty());
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn prune_after_rollback_keeps_current() {
+        let root = snapshots("snapshot-rollback", 6);
+        // Roll back to 000002, one snapshot at a time
+        for _ in 0..4 {
+            let target = previous(&root).unwrap().unwrap();
+            publish(&root, &target).unwrap();
+        }
+
+        // `current` is among the oldest: it stays, along with the newest `keep`
+        assert_eq!(prune(&root, 3).unwrap(), vec!["000001", "000003"]);
+        assert_eq!(list(&root).unwrap(), vec!["000002", "000004", "000005", "000006"]);
+        assert_eq!(current(&root).unwrap().as_deref(), Some("000002"));
+        assert!(resolve(&root).unwrap().is_dir());
+
+        assert_eq!(prune(&root, 1).unwrap(), vec!["000004", "000005"]);
+        assert_eq!(list(&root).unwrap(), vec!["000002", "000006"]);
+        assert_eq!(prune(&root, 0).unwrap(), vec!["000006"]);
+        assert_eq!(list(&root).unwrap(), vec!["000002"]);
+        let _ = fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+use crate::Pair;
+
+// Key-sorted runs of pairs, merged with a k-way heap merge. Half the budget
+// is for runs held here: past it, the largest are spilled to
+// `<dir>/run-N.bin`: repeated (len: u32 LE, key, id: u64 LE). The other half
+// is split between producers for the runs they are filling (`run_limit`).
+pub const DEFAULT_MEM_BUDGET: usize = 1 << 30;
+
+// Smallest run handed out, so a tiny budget doesn't spill one file per key
+const MIN_RUN: usize = 1 << 20;
+
+// Approximate heap cost of one pair beyond its key bytes
+const PAIR_OVERHEAD: usize = std::mem::size_of::<Pair>();
+
+pub struct ExternalSorter {
+    dir: PathBuf,
+    budget: usize,
+    in_memory: Vec<Vec<Pair>>,
+    in_memory_bytes: usize,
+    spilled: Vec<PathBuf>,
+}
+
+impl ExternalSorter {
+    /// `dir` is created on the first spill and removed when the merge is dropped.
+    pub fn new(dir: &Path, budget: usize) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            budget,
+            in_memory: vec![],
+            in_memory_bytes: 0,
+            spilled: vec![],
+        }
+    }
+
+    /// Bytes of pairs each of `workers` concurrent producers should collect
+    /// before sorting them and calling `add_run`.
+    pub fn run_limit(&self, workers: usize) -> usize {
+        (self.budget / 2 / workers.max(1)).max(MIN_RUN)
+    }
+
+    /// Adds a run, which must already be sorted by key.
+    pub fn add_run(&mut self, run: Vec<Pair>) -> anyhow::Result<()> {
+        if run.is_empty() {
+            return Ok(());
+        }
+        self.in_memory_bytes += run_bytes(&run);
+        self.in_memory.push(run);
+        while self.in_memory_bytes > self.budget / 2 {
+            let largest = (0..self.in_memory.len())
+                .max_by_key(|&i| self.in_memory[i].len())
+                .unwrap();
+            let run = self.in_memory.swap_remove(largest);
+            self.in_memory_bytes -= run_bytes(&run);
+            self.spill(&run)?;
+        }
+        Ok(())
+    }
+
+    /// Number of runs spilled to disk so far.
+    pub fn spilled(&self) -> usize {
+        self.spilled.len()
+    }
+
+    /// Merges all runs into one key-ordered stream.
+    pub fn merge(mut self) -> anyhow::Result<SortedPairs> {
+        let mut runs: Vec<Run> = self.in_memory.drain(..).map(|r| Run::Memory(r.into_iter())).collect();
+        for path in &self.spilled {
+            runs.push(Run::File(BufReader::new(fs::File::open(path)?)));
+        }
+        // The merge owns the spill files from here on
+        let spilled = std::mem::take(&mut self.spilled);
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (i, run) in runs.iter_mut().enumerate() {
+            if let Some((key, id)) = run.next()? {
+                heap.push(Reverse((key, id, i)));
+            }
+        }
+        let dir = (!spilled.is_empty()).then(|| self.dir.clone());
+        Ok(SortedPairs { runs, heap, dir })
+    }
+
+    fn spill(&mut self, run: &[Pair]) -> anyhow::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("run-{}.bin", self.spilled.len()));
+        let mut out = BufWriter::new(fs::File::create(&path)?);
+        for (key, id) in run {
+            out.write_all(&(key.len() as u32).to_le_bytes())?;
+            out.write_all(key)?;
+            out.write_all(&id.to_le_bytes())?;
+        }
+        out.flush()?;
+        self.spilled.push(path);
+        Ok(())
+    }
+}
+
+impl Drop for ExternalSorter {
+    fn drop(&mut self) {
+        if !self.spilled.is_empty() {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+fn run_bytes(run: &[Pair]) -> usize {
+    run.iter().map(pair_bytes).sum()
+}
+
+/// Approximate memory held by one pair, for filling runs up to `run_limit`.
+pub fn pair_bytes((key, _): &Pair) -> usize {
+    key.len() + PAIR_OVERHEAD
+}
+
+enum Run {
+    Memory(std::vec::IntoIter<Pair>),
+    File(BufReader<fs::File>),
+}
+
+impl Run {
+    fn next(&mut self) -> anyhow::Result<Option<Pair>> {
+        let file = match self {
+            Run::Memory(pairs) => return Ok(pairs.next()),
+            Run::File(file) => file,
+        };
+        let mut len = [0u8; 4];
+        match file.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut key = vec![0u8; u32::from_le_bytes(len) as usize];
+        file.read_exact(&mut key)?;
+        let mut id = [0u8; 8];
+        file.read_exact(&mut id)?;
+        Ok(Some((key, u64::from_le_bytes(id))))
+    }
+}
+
+/// Key-ordered pairs from `ExternalSorter::merge`. Equal keys from different
+/// runs are all yielded, ordered by ID.
+pub struct SortedPairs {
+    runs: Vec<Run>,
+    heap: BinaryHeap<Reverse<(Vec<u8>, u64, usize)>>,
+    dir: Option<PathBuf>,
+}
+
+impl Iterator for SortedPairs {
+    type Item = anyhow::Result<Pair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((key, id, i)) = self.heap.pop()?;
+        match self.runs[i].next() {
+            Ok(Some((k, v))) => self.heap.push(Reverse((k, v, i))),
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        Some(Ok((key, id)))
+    }
+}
+
+impl Drop for SortedPairs {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(keys: &[(&str, u64)]) -> Vec<Pair> {
+        keys.iter().map(|&(k, id)| (k.as_bytes().to_vec(), id)).collect()
+    }
+
+    fn sorted(budget: usize, runs: Vec<Vec<Pair>>) -> (Vec<Pair>, usize, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("sort-test-{}-{budget}", std::process::id()));
+        let mut sorter = ExternalSorter::new(&dir, budget);
+        for run in runs {
+            sorter.add_run(run).unwrap();
+        }
+        let spilled = sorter.spilled();
+        let merged = sorter.merge().unwrap().collect::<anyhow::Result<_>>().unwrap();
+        (merged, spilled, dir)
+    }
+
+    fn runs() -> Vec<Vec<Pair>> {
+        vec![
+            pairs(&[("ADA", 3), ("BTC", 1), ("ETH", 2)]),
+            pairs(&[("BNB", 11), ("ETH", 12)]),
+            vec![],
+            pairs(&[("AAVE", 20), ("ZRX", 21)]),
+        ]
+    }
+
+    fn expected() -> Vec<Pair> {
+        pairs(&[("AAVE", 20), ("ADA", 3), ("BNB", 11), ("BTC", 1), ("ETH", 2), ("ETH", 12), ("ZRX", 21)])
+    }
+
+    #[test]
+    fn merges_runs_in_memory() {
+        let (merged, spilled, _) = sorted(DEFAULT_MEM_BUDGET, runs());
+        assert_eq!(merged, expected());
+        assert_eq!(spilled, 0);
+    }
+
+    #[test]
+    fn spills_every_run_without_budget() {
+        let (merged, spilled, dir) = sorted(0, runs());
+        assert_eq!(merged, expected());
+        assert_eq!(spilled, 3);
+        assert!(!dir.exists(), "spill directory is removed after the merge");
+    }
+
+    #[test]
+    fn equal_keys_are_ordered_by_id() {
+        let (merged, _, _) = sorted(0, vec![pairs(&[("ETH", 9)]), pairs(&[("ETH", 4)]), pairs(&[("ETH", 7)])]);
+        assert_eq!(merged, pairs(&[("ETH", 4), ("ETH", 7), ("ETH", 9)]));
+    }
+
+    #[test]
+    fn run_limit_splits_half_the_budget() {
+        let sorter = ExternalSorter::new(Path::new("unused"), 64 << 20);
+        assert_eq!(sorter.run_limit(4), 8 << 20);
+        assert_eq!(sorter.run_limit(0), 32 << 20);
+        assert_eq!(ExternalSorter::new(Path::new("unused"), 0).run_limit(8), MIN_RUN);
+    }
+}
@@ -0,0 +1,497 @@
+use std::{cell::Cell, collections::HashSet, fmt, io::{self, Read}, path::Path, sync::Arc};
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
+use serde_json::error::Category;
+use crate::dedup::DedupPolicy;
+use crate::error::{BuildError, Position};
+use crate::hexkey::{HexKeys, Recognized};
+use crate::normalize::Normalize;
+use crate::report::MAX_SAMPLES;
+
+pub trait SourceParser: Send + Sync {
+    /// Recorded in the manifest next to each source.
+    fn name(&self) -> &'static str;
+    /// Bumped whenever `parse` can return different keys for the same input,
+    /// so cached key lists from older builders aren't reused.
+    fn version(&self) -> u32;
+    /// Streams `input`; memory is bounded by the keys kept, not the input size.
+    /// Malformed records are skipped and recorded in `ctx`; an error means the
+    /// input as a whole could not be read.
+    fn parse(&self, input: &mut dyn Read, ctx: &mut ParseContext) -> Result<Vec<Vec<u8>>, BuildError>;
+    /// Parser settings that change the keys `parse` returns, so cached keys
+    /// from other settings aren't reused.
+    fn cache_tag(&self) -> String {
+        String::new()
+    }
+    /// Keys are ASCII symbols, so normalization rules can apply to them.
+    /// Rules would corrupt raw byte keys such as decoded pool IDs.
+    fn symbol_keys(&self) -> bool {
+        false
+    }
+}
+
+/// `SourceParser::symbol_keys` of the parser a manifest names; false for
+/// parsers this build doesn't know.
+pub fn symbol_keys(parser: &str) -> bool {
+    let known: [&dyn SourceParser; 2] = [&BinanceParser, &UniswapParser::default()];
+    known.iter().any(|p| p.name() == parser && p.symbol_keys())
+}
+
+/// Per-file parsing state: which source and file is being read, the records
+/// rejected so far and counts for the build report.
+pub struct ParseContext {
+    source: Arc<str>,
+    path: Arc<Path>,
+    pub rejected: Vec<BuildError>,
+    /// Key records read, including rejected and duplicate ones.
+    pub parsed: u64,
+    /// Records dropped because the key was already seen.
+    pub duplicates: u64,
+    /// The first `MAX_SAMPLES` duplicates; `duplicates` counts them all.
+    pub duplicate_records: Vec<BuildError>,
+    pub dedup: DedupPolicy,
+    /// Listing times (unix seconds) from venue fields, by key.
+    pub listed: Vec<(Vec<u8>, u64)>,
+}
+
+impl ParseContext {
+    pub fn new(source: &str, path: &Path) -> Self {
+        Self {
+            source: source.into(),
+            path: path.into(),
+            rejected: vec![],
+            parsed: 0,
+            duplicates: 0,
+            duplicate_records: vec![],
+            dedup: DedupPolicy::default(),
+            listed: vec![],
+        }
+    }
+
+    pub fn reject(&mut self, at: Position, value: &str, reason: impl Into<String>) {
+        self.rejected.push(BuildError::InvalidRecord {
+            source: self.source.clone(),
+            path: self.path.clone(),
+            at,
+            value: value.to_string(),
+            reason: reason.into(),
+        });
+    }
+
+    /// Records a key seen earlier in this file; the parser drops it.
+    pub fn duplicate(&mut self, at: Position, key: &str) {
+        self.duplicates += 1;
+        if self.duplicate_records.len() < MAX_SAMPLES {
+            self.duplicate_records.push(BuildError::Duplicate {
+                source: self.source.clone(),
+                key: key.to_string(),
+                kept_in: self.source.clone(),
+                path: Some(self.path.clone()),
+                at: Some(at),
+            });
+        }
+    }
+
+    pub fn io_error(&self, message: impl fmt::Display) -> BuildError {
+        BuildError::io(self.source.clone(), self.path.clone(), message)
+    }
+
+    /// Classifies a serde_json error; `pointer` is where the parser was.
+    pub fn json_error(&self, e: serde_json::Error, pointer: String) -> BuildError {
+        let (source, path) = (self.source.clone(), self.path.clone());
+        let at = Position { pointer, line: e.line(), column: e.column() };
+        match e.classify() {
+            Category::Io => BuildError::Io { source, path, message: e.to_string() },
+            Category::Syntax | Category::Eof => BuildError::Syntax { source, path, at, message: e.to_string() },
+            Category::Data => BuildError::Shape { source, path, at, message: e.to_string() },
+        }
+    }
+}
+
+pub struct BinanceParser;
+
+/// Collects hex keys from anywhere in the document; `hex` sets which
+/// spellings count. Addresses count only as object keys and `id` values,
+/// so token and owner fields don't become topics.
+#[derive(Default)]
+pub struct UniswapParser {
+    pub hex: HexKeys,
+}
+
+impl SourceParser for BinanceParser {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    // 2: empty and whitespace symbols are rejected
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn parse(&self, input: &mut dyn Read, ctx: &mut ParseContext) -> Result<Vec<Vec<u8>>, BuildError> {
+        let pos = Cell::new((1, 0));
+        let mut de = serde_json::Deserializer::from_reader(Tracked { inner: input, pos: &pos });
+        let mut symbols = de.deserialize_map(SymbolKeys { ctx: &mut *ctx, pos: &pos })
+            .and_then(|symbols| de.end().map(|()| symbols))
+            .map_err(|e| ctx.json_error(e, String::new()))?;
+
+        // Same order as a parsed `serde_json::Map` (a BTreeMap), so IDs match
+        // builds that loaded the whole document
+        symbols.sort_unstable();
+        Ok(symbols)
+    }
+
+    fn symbol_keys(&self) -> bool {
+        true
+    }
+}
+
+impl SourceParser for UniswapParser {
+    fn name(&self) -> &'static str {
+        "uniswap"
+    }
+
+    // 2: keys depend on `HexKeys`, malformed pool IDs are rejected
+    // 3: addresses count only in pool slots
+    fn version(&self) -> u32 {
+        3
+    }
+
+    fn parse(&self, input: &mut dyn Read, ctx: &mut ParseContext) -> Result<Vec<Vec<u8>>, BuildError> {
+        let pos = Cell::new((1, 0));
+        let mut walk = Walk { ctx: &mut *ctx, hex: self.hex, pos: &pos, pointer: vec![], ids: HashSet::new(), records: vec![] };
+        let mut de = serde_json::Deserializer::from_reader(Tracked { inner: input, pos: &pos });
+        let result = B32Collector(&mut walk).deserialize(&mut de).and_then(|()| de.end());
+        if let Err(e) = result {
+            let pointer = walk.pointer();
+            return Err(walk.ctx.json_error(e, pointer));
+        }
+
+        // Sort for stable ID assignment within source (like before)
+        let mut pools: Vec<Vec<u8>> = walk.ids.into_iter().collect();
+        pools.sort_unstable();
+
+        // IMPORTANT: store raw bytes (no "0x", no hex encoding)
+        Ok(pools)
+    }
+
+    fn cache_tag(&self) -> String {
+        self.hex.tag()
+    }
+}
+
+
+pub struct Source<'a> {
+    pub name: &'a str,
+    pub parser: Box<dyn SourceParser>,
+    pub path: &'a str,
+    pub id_start: u64,
+    pub dedup: DedupPolicy,
+    pub normalize: Option<Normalize>,
+}
+
+// Counts lines and columns of the bytes the deserializer has consumed, so
+// visitors can report where a record ends.
+struct Tracked<'p, R> {
+    inner: R,
+    pos: &'p Cell<(usize, usize)>,
+}
+
+impl<R: Read> Read for Tracked<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let (mut line, mut column) = self.pos.get();
+        for &b in &buf[..n] {
+            if b == b'\n' {
+                line += 1;
+                column = 0;
+            } else {
+                column += 1;
+            }
+        }
+        self.pos.set((line, column));
+        Ok(n)
+    }
+}
+
+fn position(pos: &Cell<(usize, usize)>, pointer: String) -> Position {
+    let (line, column) = pos.get();
+    Position { pointer, line, column }
+}
+
+fn pointer_token(s: &str) -> String {
+    s.replace('~', "~0").replace('/', "~1")
+}
+
+// Top-level object keys; values are skipped without being materialized,
+// except for their `onboardDate` (unix ms) listing time.
+struct SymbolKeys<'a> {
+    ctx: &'a mut ParseContext,
+    pos: &'a Cell<(usize, usize)>,
+}
+
+impl<'de> Visitor<'de> for SymbolKeys<'_> {
+    type Value = Vec<Vec<u8>>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Expected JSON object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut keys = vec![];
+        let mut seen = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            let at = position(self.pos, format!("/{}", pointer_token(&key)));
+            let onboard = map.next_value_seed(OnboardDate)?;
+            self.ctx.parsed += 1;
+            if key.is_empty() {
+                self.ctx.reject(at, &key, "empty symbol");
+            } else if key.chars().any(|c| c.is_whitespace() || c.is_control()) {
+                self.ctx.reject(at, &key, "symbol contains whitespace or control characters");
+            } else if !seen.insert(key.clone()) {
+                self.ctx.duplicate(at, &key);
+            } else {
+                if let Some(ms) = onboard {
+                    self.ctx.listed.push((key.clone().into_bytes(), ms / 1000));
+                }
+                keys.push(key.into_bytes());
+            }
+        }
+        Ok(keys)
+    }
+}
+
+// `onboardDate` of a symbol's value, if it is an object that has one.
+// Anything else is skipped.
+struct OnboardDate;
+
+impl<'de> DeserializeSeed<'de> for OnboardDate {
+    type Value = Option<u64>;
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<Option<u64>, D::Error> {
+        de.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for OnboardDate {
+    type Value = Option<u64>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Option<u64>, A::Error> {
+        let mut onboard = None;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "onboardDate" {
+                onboard = map.next_value::<serde_json::Value>()?.as_u64();
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        Ok(onboard)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Option<u64>, A::Error> {
+        while seq.next_element::<IgnoredAny>()?.is_some() {}
+        Ok(None)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<Option<u64>, E> { Ok(None) }
+    fn visit_bool<E>(self, _: bool) -> Result<Option<u64>, E> { Ok(None) }
+    fn visit_i64<E>(self, _: i64) -> Result<Option<u64>, E> { Ok(None) }
+    fn visit_u64<E>(self, _: u64) -> Result<Option<u64>, E> { Ok(None) }
+    fn visit_f64<E>(self, _: f64) -> Result<Option<u64>, E> { Ok(None) }
+    fn visit_unit<E>(self) -> Result<Option<u64>, E> { Ok(None) }
+}
+
+// Collector state: pools found so far, the JSON pointer being visited and
+// the pools naming the records it is inside.
+struct Walk<'a> {
+    ctx: &'a mut ParseContext,
+    hex: HexKeys,
+    pos: &'a Cell<(usize, usize)>,
+    pointer: Vec<String>,
+    ids: HashSet<Vec<u8>>,
+    records: Vec<Vec<u8>>,
+}
+
+impl Walk<'_> {
+    fn pointer(&self) -> String {
+        self.pointer.iter().map(|t| format!("/{t}")).collect()
+    }
+
+    // `pool_slot`: the string sits where pool IDs go (an object key or an
+    // `id` field), so a 0x string there that isn't a pool ID is malformed.
+    // Elsewhere only near misses of a key are. A key repeated inside the
+    // record it names (`{"0xab..": {"id": "0xab.."}}`) is the same record;
+    // only a repeat in another record is a duplicate. 20-byte addresses
+    // are only keys in pool slots. Returns the key.
+    fn candidate(&mut self, s: &str, pool_slot: bool) -> Option<Vec<u8>> {
+        let hex = HexKeys { addresses: self.hex.addresses && pool_slot, ..self.hex };
+        match hex.recognize(s) {
+            Recognized::Key(key) if self.records.contains(&key) => return Some(key),
+            Recognized::Key(key) => {
+                self.ctx.parsed += 1;
+                if self.ids.contains(&key) {
+                    self.ctx.duplicate(position(self.pos, self.pointer()), s);
+                } else {
+                    self.ids.insert(key.clone());
+                }
+                return Some(key);
+            }
+            Recognized::NearMiss(reason) => {
+                self.ctx.parsed += 1;
+                self.ctx.reject(position(self.pos, self.pointer()), s, reason);
+            }
+            Recognized::None if pool_slot && (s.starts_with("0x") || s.starts_with("0X")) => {
+                self.ctx.parsed += 1;
+                let reason = if !s[2..].chars().all(|c| c.is_ascii_hexdigit()) {
+                    "non-hex digits in pool ID".to_string()
+                } else {
+                    format!("pool ID has {} hex digits, expected 0x + 64", s.len() - 2)
+                };
+                self.ctx.reject(position(self.pos, self.pointer()), s, reason);
+            }
+            Recognized::None => {}
+        }
+        None
+    }
+}
+
+// Walks any JSON value, collecting every string (object keys included) that
+// is a hex key.
+struct B32Collector<'w, 'a>(&'w mut Walk<'a>);
+
+impl<'de> DeserializeSeed<'de> for B32Collector<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, de: D) -> Result<(), D::Error> {
+        de.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for B32Collector<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("any JSON value")
+    }
+
+    fn visit_str<E>(self, s: &str) -> Result<(), E> {
+        let pool_slot = self.0.pointer.last().is_some_and(|t| t == "id");
+        let key = self.0.candidate(s, pool_slot);
+        // An `id` names the object it is in, for the rest of that object
+        if let (true, Some(key)) = (pool_slot, key) {
+            self.0.records.push(key);
+        }
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut i = 0usize;
+        loop {
+            self.0.pointer.push(i.to_string());
+            if seq.next_element_seed(B32Collector(self.0))?.is_none() {
+                self.0.pointer.pop();
+                return Ok(());
+            }
+            self.0.pointer.pop();
+            i += 1;
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let records = self.0.records.len();
+        while let Some(key) = map.next_key::<String>()? {
+            self.0.pointer.push(pointer_token(&key));
+            // A pool key names its value
+            let pool = self.0.candidate(&key, true);            // scan key
+            if let Some(pool) = &pool {
+                self.0.records.push(pool.clone());
+            }
+            map.next_value_seed(B32Collector(self.0))?;         // scan value
+            if pool.is_some() {
+                self.0.records.pop();
+            }
+            self.0.pointer.pop();
+        }
+        self.0.records.truncate(records);
+        Ok(())
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> { Ok(()) }
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> { Ok(()) }
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> { Ok(()) }
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> { Ok(()) }
+    fn visit_unit<E>(self) -> Result<(), E> { Ok(()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: &str = "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+    const B: &str = "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+
+    fn parse(json: &str) -> (Vec<Vec<u8>>, ParseContext) {
+        parse_with(HexKeys::default(), json)
+    }
+
+    fn parse_with(hex: HexKeys, json: &str) -> (Vec<Vec<u8>>, ParseContext) {
+        let mut ctx = ParseContext::new("uniswap", Path::new("test.json"));
+        let keys = UniswapParser { hex }.parse(&mut json.as_bytes(), &mut ctx).unwrap();
+        (keys, ctx)
+    }
+
+    #[test]
+    fn pool_id_inside_its_own_record_is_not_a_duplicate() {
+        let (keys, ctx) = parse(&format!(r#"{{"{A}": {{"id": "{A}", "pair": {{"pool": "{A}"}}}}, "{B}": {{}}}}"#));
+        assert_eq!(keys.len(), 2);
+        assert_eq!((ctx.parsed, ctx.duplicates), (2, 0));
+
+        let (keys, ctx) = parse(&format!(r#"{{"pools": [{{"id": "{A}", "self": "{A}"}}, {{"id": "{B}"}}]}}"#));
+        assert_eq!(keys.len(), 2);
+        assert_eq!((ctx.parsed, ctx.duplicates), (2, 0));
+    }
+
+    #[test]
+    fn pool_in_two_records_is_a_duplicate() {
+        let (keys, ctx) = parse(&format!(r#"{{"pools": [{{"id": "{A}"}}, {{"id": "{A}"}}], "{B}": {{"id": "{B}"}}}}"#));
+        assert_eq!(keys.len(), 2);
+        assert_eq!((ctx.parsed, ctx.duplicates), (3, 1));
+
+        // A record's id names it only until the record ends
+        let (_, ctx) = parse(&format!(r#"{{"{A}": {{"id": "{A}"}}, "x": {{"id": "{A}"}}}}"#));
+        assert_eq!((ctx.parsed, ctx.duplicates), (2, 1));
+    }
+
+    #[test]
+    fn addresses_count_only_in_pool_slots() {
+        let hex = HexKeys { addresses: true, ..HexKeys::default() };
+        let pool = "0x1111111111111111111111111111111111111111";
+        let token0 = "0x2222222222222222222222222222222222222222";
+        let token1 = "0x3333333333333333333333333333333333333333";
+        let json = format!(
+            r#"{{"{pool}": {{"token0": "{token0}", "token1": "{token1}"}}, "pools": [{{"id": "{A}", "token0": "{token0}"}}]}}"#
+        );
+        let (keys, ctx) = parse_with(hex, &json);
+        assert_eq!(keys, vec![hex::decode(&pool[2..]).unwrap(), hex::decode(&A[2..]).unwrap()]);
+        assert_eq!((ctx.parsed, ctx.duplicates), (2, 0));
+
+        // An address in an `id` field is a pool
+        let (keys, _) = parse_with(hex, &format!(r#"{{"pools": [{{"id": "{pool}", "owner": "{token0}"}}]}}"#));
+        assert_eq!(keys, vec![hex::decode(&pool[2..]).unwrap()]);
+    }
+
+    #[test]
+    fn duplicates_past_the_sample_are_counted() {
+        let mut ctx = ParseContext::new("uniswap", Path::new("test.json"));
+        ctx.dedup = DedupPolicy::Error;
+        let records: Vec<String> = (0..MAX_SAMPLES + 5).map(|_| format!(r#"{{"id": "{A}"}}"#)).collect();
+        let json = format!(r#"{{"pools": [{}]}}"#, records.join(","));
+        let keys = UniswapParser::default().parse(&mut json.as_bytes(), &mut ctx).unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(ctx.duplicates, MAX_SAMPLES as u64 + 4);
+        assert_eq!(ctx.duplicate_records.len(), MAX_SAMPLES);
+    }
+}
@@ -0,0 +1,43 @@
+// Snapshots for unit tests, written under the system temp directory.
+use std::{fs, path::{Path, PathBuf}};
+use crate::manifest::{Manifest, SourceEntry};
+use crate::validity::History;
+use crate::{writer, TopicMap};
+
+/// A fresh, empty directory named after the test.
+pub fn root(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("topic-map-test-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    root
+}
+
+/// Manifest built at `now` with `sources` as (name, id_start, id_end).
+pub fn manifest(now: u64, sources: &[(&str, u64, u64)]) -> Manifest {
+    let mut manifest = Manifest::new(now);
+    for &(name, id_start, id_end) in sources {
+        manifest.sources.insert(name.to_string(), SourceEntry {
+            parser: name.to_string(),
+            count: 0,
+            id_start,
+            id_end,
+            input_blake3: None,
+            normalize: None,
+        });
+    }
+    manifest
+}
+
+/// Publishes a snapshot of `keys` (any order) under `root` and opens it.
+pub fn snapshot(
+    root: &Path,
+    manifest: Manifest,
+    keys: &[(&str, u64)],
+    history: Option<History>,
+) -> TopicMap {
+    let mut pairs: Vec<(Vec<u8>, u64)> = keys.iter().map(|&(k, id)| (k.as_bytes().to_vec(), id)).collect();
+    pairs.sort();
+    let mut manifest = manifest;
+    let written = writer::write_snapshot(root, &mut manifest, pairs.into_iter().map(Ok), vec![], history, false).unwrap();
+    TopicMap::open(&written.dir).unwrap()
+}
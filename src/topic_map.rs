@@ -0,0 +1,180 @@
+use std::path::{Path, PathBuf};
+use fst::{Map, Streamer};
+use crate::bytes::{Bytes, LoadMode};
+use crate::dense::{self, DenseIds};
+use crate::manifest::Manifest;
+use crate::reverse::{self, ReverseIndex};
+use crate::sources;
+use crate::validity::Validity;
+use crate::{snapshot, verify, Pair};
+
+pub const MAP_FILE: &str = "topic.map.fst";
+
+/// Read side of a snapshot directory: the key -> topic ID FST, the reverse
+/// index, dense ID and validity tables if the snapshot has them,
+/// and the manifest.
+///
+/// Files are memory-mapped by default (see `LoadMode`), so processes on one
+/// host share a single page-cache copy of the snapshot.
+pub struct TopicMap {
+    map: Map<Bytes>,
+    reverse: Option<ReverseIndex>,
+    dense: Option<(Map<Bytes>, DenseIds)>,
+    validity: Option<Validity>,
+    manifest: Manifest,
+    dir: PathBuf,
+}
+
+impl TopicMap {
+    /// Loads without checking digests. Prefer `open_verified` when serving.
+    /// `dir` is either a snapshot directory or a root with a `current` pointer.
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        Self::open_with(dir, LoadMode::default())
+    }
+
+    pub fn open_with(dir: &Path, mode: LoadMode) -> anyhow::Result<Self> {
+        let dir = snapshot::resolve(dir)?;
+        let manifest = Manifest::read(&dir)?;
+        Self::load(dir, manifest, mode)
+    }
+
+    /// Verifies every file of the snapshot against the manifest before loading.
+    pub fn open_verified(dir: &Path) -> anyhow::Result<Self> {
+        Self::open_verified_with(dir, LoadMode::default())
+    }
+
+    pub fn open_verified_with(dir: &Path, mode: LoadMode) -> anyhow::Result<Self> {
+        // Resolve once: published snapshot directories are never modified,
+        // so what was verified is what gets loaded.
+        let dir = snapshot::resolve(dir)?;
+        let manifest = verify::verify_snapshot(&dir)?;
+        Self::load(dir, manifest, mode)
+    }
+
+    fn load(dir: PathBuf, manifest: Manifest, mode: LoadMode) -> anyhow::Result<Self> {
+        let map = load_fst(&dir.join(MAP_FILE), mode)?;
+        let reverse = match manifest.file(reverse::REVERSE_FILE) {
+            Some(_) => Some(ReverseIndex::open(&dir.join(reverse::REVERSE_FILE), mode)?),
+            None => None,
+        };
+        let dense = match manifest.dense {
+            Some(_) => Some((
+                load_fst(&dir.join(dense::DENSE_FST_FILE), mode)?,
+                DenseIds::open(&dir.join(dense::DENSE_IDS_FILE), mode)?,
+            )),
+            None => None,
+        };
+        let validity = match manifest.validity {
+            Some(_) => Some(Validity::open(&dir, mode)?),
+            None => None,
+        };
+        Ok(Self { map, reverse, dense, validity, manifest, dir })
+    }
+
+    #[inline]
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Option<u64> {
+        self.map.get(key)
+    }
+
+    /// Like `get`, but also accepts other spellings of a key: `key` under each
+    /// source's normalization rules (e.g. `eth-usdt` for `ETHUSDT`). A
+    /// normalized match only counts within the ID range of the source whose
+    /// rules produced it. Sources without symbol keys are skipped.
+    pub fn normalize_and_get<K: AsRef<[u8]>>(&self, key: K) -> Option<u64> {
+        let key = key.as_ref();
+        if let Some(id) = self.map.get(key) {
+            return Some(id);
+        }
+        self.manifest.sources.values()
+            .filter(|s| sources::symbol_keys(&s.parser))
+            .find_map(|s| {
+                let normalized = s.normalize.as_ref()?.apply(key);
+                self.map.get(normalized).filter(|id| (s.id_start..s.id_end).contains(id))
+            })
+    }
+
+    /// Topic ID of `key` at unix time `t`: `None` if the key wasn't listed
+    /// then, even if it is now, and the ID it had then if it was delisted or
+    /// re-IDed since. Snapshots without a validity table (e.g. merges of
+    /// snapshots without one) treat every key in the map as always live.
+    pub fn get_as_of<K: AsRef<[u8]>>(&self, key: K, t: u64) -> Option<u64> {
+        match &self.validity {
+            Some(validity) => validity.as_of(key, t),
+            None => self.get(key),
+        }
+    }
+
+    pub fn validity(&self) -> Option<&Validity> {
+        self.validity.as_ref()
+    }
+
+    /// Key of topic `id`. Snapshots built before the reverse index existed
+    /// fall back to a full scan of the FST.
+    pub fn key_of(&self, id: u64) -> Option<Vec<u8>> {
+        if let Some(rev) = &self.reverse {
+            return rev.key(id).map(<[u8]>::to_vec);
+        }
+        let mut stream = self.map.stream();
+        while let Some((key, v)) = stream.next() {
+            if v == id {
+                return Some(key.to_vec());
+            }
+        }
+        None
+    }
+
+    /// Dense u32 ID of `key`, if the snapshot was built with `--dense`.
+    #[inline]
+    pub fn get_dense<K: AsRef<[u8]>>(&self, key: K) -> Option<u32> {
+        self.dense.as_ref()?.0.get(key).map(|id| id as u32)
+    }
+
+    pub fn dense_ids(&self) -> Option<&DenseIds> {
+        self.dense.as_ref().map(|(_, ids)| ids)
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// The resolved snapshot directory this map was loaded from.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Whether the main FST is memory-mapped (false after a heap fallback).
+    pub fn is_mmap(&self) -> bool {
+        self.map.as_fst().as_inner().is_mmap()
+    }
+
+    pub fn as_fst(&self) -> &Map<Bytes> {
+        &self.map
+    }
+
+    /// (key, ID) for each alias ID: one the reverse index resolves to a key
+    /// that maps to another ID.
+    pub fn aliases(&self) -> Vec<Pair> {
+        let (Some(_), Some(rev)) = (&self.manifest.aliases, &self.reverse) else {
+            return vec![];
+        };
+        rev.iter()
+            .filter(|&(id, key)| self.get(key) != Some(id))
+            .map(|(id, key)| (key.to_vec(), id))
+            .collect()
+    }
+}
+
+fn load_fst(path: &Path, mode: LoadMode) -> anyhow::Result<Map<Bytes>> {
+    let bytes = Bytes::load(path, mode)?;
+    Map::new(bytes).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+}
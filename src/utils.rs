@@ -0,0 +1,25 @@
+use crate::hexkey::{HexKeys, Recognized};
+
+/// Printable form of a key: ASCII symbols as is, binary keys as 0x-hex.
+pub fn display_key(key: &[u8]) -> String {
+    let symbol_byte = |b: &u8| b.is_ascii_alphanumeric() || b"-_/.:".contains(b);
+    if !key.is_empty() && key.iter().all(symbol_byte) {
+        String::from_utf8_lossy(key).into_owned()
+    } else {
+        format!("0x{}", hex::encode(key))
+    }
+}
+
+/// Key bytes for user input: hex keys in any spelling `HexKeys::all` accepts
+/// (0x/0X or no prefix, 32 bytes or a 20-byte address) are decoded to raw
+/// bytes (how they are stored), anything else is taken as an ASCII symbol.
+pub fn parse_key(s: &str) -> anyhow::Result<Vec<u8>> {
+    match HexKeys::all().recognize(s) {
+        Recognized::Key(key) => Ok(key),
+        Recognized::NearMiss(reason) => anyhow::bail!("{s}: {reason}"),
+        Recognized::None if s.starts_with("0x") && s[2..].chars().all(|c| c.is_ascii_hexdigit()) => {
+            anyhow::bail!("{s}: hex keys must be 32 bytes (64 hex digits) or 20-byte addresses (40)")
+        }
+        Recognized::None => Ok(s.as_bytes().to_vec()),
+    }
+}
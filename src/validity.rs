@@ -0,0 +1,362 @@
+use std::{collections::{BTreeMap, HashMap}, fs, io::{BufWriter, Write}, path::Path};
+use fst::{IntoStreamer, Map, MapBuilder, Streamer};
+use crate::bytes::{Bytes, LoadMode};
+use crate::manifest::ValidityEntry;
+use crate::TopicMap;
+
+// When each key was live, including keys no longer in the map, so backtests
+// can ask what existed at a given time. The FST maps each key to the index
+// of its first interval. Layout of VALIDITY_FILE (all little-endian):
+//   count: u64
+//   count x (id: u64, from: u64, until: u64)   unix seconds, grouped by key
+//                                              in key order, oldest first;
+//                                              until is u64::MAX while live
+pub const VALIDITY_FST_FILE: &str = "topic.validity.fst";
+pub const VALIDITY_FILE: &str = "topic.validity.bin";
+
+const ENTRY: usize = 24;
+const OPEN: u64 = u64::MAX;
+
+/// A key was live from `from` (inclusive) until `until` (exclusive, `None`
+/// while still live) under topic `id`. A relisted key gets a new interval,
+/// possibly with another ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub id: u64,
+    pub from: u64,
+    pub until: Option<u64>,
+}
+
+impl Interval {
+    pub fn contains(&self, t: u64) -> bool {
+        self.from <= t && self.until.is_none_or(|until| t < until)
+    }
+}
+
+/// What a build knows about key lifetimes besides its own keys.
+#[derive(Default)]
+pub struct History {
+    // Intervals carried over, in key order
+    previous: Vec<(Vec<u8>, Vec<Interval>)>,
+    // When the previous build last looked; a key new since then can't have
+    // been listed before it
+    since: u64,
+    /// Listing times from venue fields, used for keys listed since the
+    /// previous snapshot was built.
+    pub listed: HashMap<Vec<u8>, u64>,
+}
+
+impl History {
+    /// Carries over the intervals of `previous`, the snapshot being replaced.
+    pub fn new(previous: Option<&TopicMap>, listed: HashMap<Vec<u8>, u64>) -> Self {
+        let since = previous.and_then(|prev| prev.manifest().built_at).unwrap_or(0);
+        let previous = previous.map(intervals).unwrap_or_default();
+        Self { previous, since, listed }
+    }
+
+    /// Unions the intervals of merge inputs, most preferred first: each key
+    /// keeps the intervals of the first input that has it. `None` when no
+    /// input has a validity table, as there is no history to keep.
+    pub fn merged<'a>(inputs: impl IntoIterator<Item = &'a TopicMap>) -> Option<Self> {
+        let inputs: Vec<&TopicMap> = inputs.into_iter().collect();
+        if inputs.iter().all(|input| input.validity().is_none()) {
+            return None;
+        }
+        let mut keys = BTreeMap::new();
+        for input in &inputs {
+            for (key, intervals) in intervals(input) {
+                keys.entry(key).or_insert(intervals);
+            }
+        }
+        let since = inputs.iter().filter_map(|input| input.manifest().built_at).max().unwrap_or(0);
+        Some(Self { previous: keys.into_iter().collect(), since, listed: HashMap::new() })
+    }
+}
+
+// A snapshot's table in key order. One without a table is seeded with its
+// keys as live since it was built.
+fn intervals(map: &TopicMap) -> Vec<(Vec<u8>, Vec<Interval>)> {
+    if let Some(v) = map.validity() {
+        return v.entries();
+    }
+    let from = map.manifest().built_at.unwrap_or(0);
+    let mut seeded = vec![];
+    let mut stream = map.as_fst().stream();
+    while let Some((key, id)) = stream.next() {
+        seeded.push((key.to_vec(), vec![Interval { id, from, until: None }]));
+    }
+    seeded
+}
+
+pub struct Validity {
+    map: Map<Bytes>,
+    bytes: Bytes,
+    len: usize,
+}
+
+impl Validity {
+    pub fn open(dir: &Path, mode: LoadMode) -> anyhow::Result<Self> {
+        let path = dir.join(VALIDITY_FST_FILE);
+        let map = Map::new(Bytes::load(&path, mode)?)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        let path = dir.join(VALIDITY_FILE);
+        let bytes = Bytes::load(&path, mode)?;
+        let data = bytes.as_ref();
+        let len = data.get(..8)
+            .map(|b| u64::from_le_bytes(b.try_into().unwrap()) as usize)
+            .ok_or_else(|| anyhow::anyhow!("{}: truncated header", path.display()))?;
+        if data.len() < 8 + len.saturating_mul(ENTRY) {
+            anyhow::bail!("{}: truncated table for {len} intervals", path.display());
+        }
+        Ok(Self { map, bytes, len })
+    }
+
+    /// Intervals in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Keys in the table, live or not.
+    pub fn keys(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Every interval of `key`, oldest first.
+    pub fn intervals<K: AsRef<[u8]>>(&self, key: K) -> Option<Vec<Interval>> {
+        let key = key.as_ref();
+        let first = self.map.get(key)? as usize;
+        let end = self.map.range().gt(key).into_stream().next()
+            .map_or(self.len, |(_, i)| i as usize);
+        Some((first..end.min(self.len)).map(|i| self.entry(i)).collect())
+    }
+
+    /// Topic ID of `key` at unix time `t`, if it was live then.
+    pub fn as_of<K: AsRef<[u8]>>(&self, key: K, t: u64) -> Option<u64> {
+        self.intervals(key)?.iter().find(|i| i.contains(t)).map(|i| i.id)
+    }
+
+    fn entry(&self, i: usize) -> Interval {
+        let at = 8 + i * ENTRY;
+        let data = self.bytes.as_ref();
+        let word = |n: usize| u64::from_le_bytes(data[at + n * 8..at + n * 8 + 8].try_into().unwrap());
+        let until = word(2);
+        Interval { id: word(0), from: word(1), until: (until != OPEN).then_some(until) }
+    }
+
+    // Whole table in key order
+    fn entries(&self) -> Vec<(Vec<u8>, Vec<Interval>)> {
+        let mut firsts = vec![];
+        let mut stream = self.map.stream();
+        while let Some((key, first)) = stream.next() {
+            firsts.push((key.to_vec(), first as usize));
+        }
+        let ends: Vec<usize> = firsts.iter().skip(1).map(|&(_, first)| first).chain([self.len]).collect();
+        firsts.into_iter().zip(ends)
+            .map(|((key, first), end)| (key, (first..end.min(self.len)).map(|i| self.entry(i)).collect()))
+            .collect()
+    }
+}
+
+/// Writes the table for a map built at `now`: the history's intervals,
+/// closed for keys that are gone or changed ID, and opened for keys that are
+/// new or back.
+pub fn write<D: AsRef<[u8]>>(
+    dir: &Path,
+    map: &Map<D>,
+    now: u64,
+    history: History,
+) -> anyhow::Result<ValidityEntry> {
+    let History { previous, since, listed } = history;
+
+    let path = dir.join(VALIDITY_FST_FILE);
+    let mut builder = MapBuilder::new(BufWriter::new(fs::File::create(&path)?))?;
+    let mut table: Vec<Interval> = vec![];
+    let mut keys = 0u64;
+    let mut old = previous.into_iter().peekable();
+    let mut stream = map.stream();
+    let mut new = stream.next().map(|(k, id)| (k.to_vec(), id));
+    loop {
+        // Merge-join the previous table with the new map, both key-ordered
+        let take_old = match (old.peek(), &new) {
+            (None, None) => break,
+            (Some((o, _)), Some((n, _))) => o <= n,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+        };
+        let (key, mut intervals) = if take_old { old.next().unwrap() } else { (new.as_ref().unwrap().0.clone(), vec![]) };
+        let id = match &new {
+            Some((n, id)) if *n == key => {
+                let id = *id;
+                new = stream.next().map(|(k, id)| (k.to_vec(), id));
+                Some(id)
+            }
+            _ => None,
+        };
+        update(&mut intervals, id, now, listed.get(&key).copied().filter(|&t| t > since));
+        builder.insert(&key, table.len() as u64)
+            .map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+        table.extend(intervals);
+        keys += 1;
+    }
+    builder.finish()?;
+
+    let mut out = BufWriter::new(fs::File::create(dir.join(VALIDITY_FILE))?);
+    out.write_all(&(table.len() as u64).to_le_bytes())?;
+    for i in &table {
+        out.write_all(&i.id.to_le_bytes())?;
+        out.write_all(&i.from.to_le_bytes())?;
+        out.write_all(&i.until.unwrap_or(OPEN).to_le_bytes())?;
+    }
+    out.flush()?;
+    Ok(ValidityEntry { keys, intervals: table.len() as u64 })
+}
+
+// `id` is the key's ID in the new map, `None` if it is not in it; `listed`
+// a venue listing time since the previous build.
+fn update(intervals: &mut Vec<Interval>, id: Option<u64>, now: u64, listed: Option<u64>) {
+    let live = intervals.last().filter(|i| i.until.is_none()).map(|i| i.id);
+    if live.is_some() && live == id {
+        return;
+    }
+    if live.is_some() {
+        intervals.last_mut().unwrap().until = Some(now);
+    }
+    let Some(id) = id else { return };
+    let after = intervals.last().and_then(|i| i.until).unwrap_or(0);
+    let from = listed.filter(|&t| t >= after && t <= now).unwrap_or(now);
+    intervals.push(Interval { id, from, until: None });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::Manifest;
+    use crate::testutil;
+
+    fn live(id: u64, from: u64) -> Interval {
+        Interval { id, from, until: None }
+    }
+
+    fn closed(id: u64, from: u64, until: u64) -> Interval {
+        Interval { id, from, until: Some(until) }
+    }
+
+    // Writes a snapshot of `keys` built at `now` under `root`
+    fn snapshot(root: &Path, now: u64, keys: &[(&str, u64)], history: Option<History>) -> TopicMap {
+        testutil::snapshot(root, Manifest::new(now), keys, history)
+    }
+
+    fn root(name: &str) -> std::path::PathBuf {
+        testutil::root(&format!("validity-{name}"))
+    }
+
+    #[test]
+    fn new_key_opens_at_build_or_listing_time() {
+        let mut intervals = vec![];
+        update(&mut intervals, Some(7), 200, None);
+        assert_eq!(intervals, [live(7, 200)]);
+
+        let mut intervals = vec![];
+        update(&mut intervals, Some(7), 200, Some(150));
+        assert_eq!(intervals, [live(7, 150)]);
+
+        // A listing time after the build can't be right
+        let mut intervals = vec![];
+        update(&mut intervals, Some(7), 200, Some(250));
+        assert_eq!(intervals, [live(7, 200)]);
+    }
+
+    #[test]
+    fn unchanged_key_keeps_its_interval() {
+        let mut intervals = vec![live(7, 100)];
+        update(&mut intervals, Some(7), 200, Some(150));
+        assert_eq!(intervals, [live(7, 100)]);
+    }
+
+    #[test]
+    fn delisted_key_is_closed() {
+        let mut intervals = vec![live(7, 100)];
+        update(&mut intervals, None, 200, None);
+        assert_eq!(intervals, [closed(7, 100, 200)]);
+
+        update(&mut intervals, None, 300, None);
+        assert_eq!(intervals, [closed(7, 100, 200)]);
+    }
+
+    #[test]
+    fn relisted_key_gets_a_new_interval() {
+        let mut intervals = vec![closed(7, 100, 200)];
+        update(&mut intervals, Some(7), 300, None);
+        assert_eq!(intervals, [closed(7, 100, 200), live(7, 300)]);
+
+        // Not before it was delisted
+        let mut intervals = vec![closed(7, 100, 200)];
+        update(&mut intervals, Some(7), 300, Some(150));
+        assert_eq!(intervals, [closed(7, 100, 200), live(7, 300)]);
+    }
+
+    #[test]
+    fn re_ided_key_closes_and_reopens() {
+        let mut intervals = vec![live(7, 100)];
+        update(&mut intervals, Some(9), 200, None);
+        assert_eq!(intervals, [closed(7, 100, 200), live(9, 200)]);
+    }
+
+    #[test]
+    fn carries_over_across_builds() {
+        let root = root("builds");
+        // No validity table: seeded with its keys live since it was built
+        let first = snapshot(&root, 100, &[("ETH", 1), ("BTC", 2)], None);
+        assert!(first.validity().is_none());
+
+        let second = snapshot(&root, 200, &[("ETH", 1), ("SOL", 3)], Some(History::new(Some(&first), HashMap::new())));
+        let v = second.validity().unwrap();
+        assert_eq!(v.intervals("ETH").unwrap(), [live(1, 100)]);
+        assert_eq!(v.intervals("BTC").unwrap(), [closed(2, 100, 200)]);
+        assert_eq!(v.intervals("SOL").unwrap(), [live(3, 200)]);
+        assert_eq!((v.keys(), v.len()), (3, 3));
+
+        let listed = HashMap::from([(b"ADA".to_vec(), 250), (b"ETH".to_vec(), 50)]);
+        let third = snapshot(&root, 300, &[("ETH", 4), ("BTC", 2), ("ADA", 5)], Some(History::new(Some(&second), listed)));
+        let v = third.validity().unwrap();
+        assert_eq!(v.intervals("ETH").unwrap(), [closed(1, 100, 300), live(4, 300)]);
+        assert_eq!(v.intervals("BTC").unwrap(), [closed(2, 100, 200), live(2, 300)]);
+        assert_eq!(v.intervals("SOL").unwrap(), [closed(3, 200, 300)]);
+        assert_eq!(v.intervals("ADA").unwrap(), [live(5, 250)]);
+        assert_eq!(v.intervals("XRP"), None);
+
+        assert_eq!(third.get_as_of("ETH", 150), Some(1));
+        assert_eq!(third.get_as_of("ETH", 300), Some(4));
+        assert_eq!(third.get_as_of("BTC", 250), None);
+        assert_eq!(third.get_as_of("SOL", 250), Some(3));
+        assert_eq!(third.get_as_of("ADA", 249), None);
+        let _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn merges_inputs_preferring_the_first() {
+        let (left, right) = (root("merge-left"), root("merge-right"));
+        let seed = snapshot(&left, 100, &[("ETH", 1), ("BTC", 2)], None);
+        let cex = snapshot(&left, 200, &[("ETH", 1)], Some(History::new(Some(&seed), HashMap::new())));
+        // No table: its keys count as live since it was built
+        let defi = snapshot(&right, 150, &[("ETH", 9), ("UNI", 10)], None);
+
+        assert!(History::merged([&defi]).is_none());
+        let history = History::merged([&cex, &defi]).unwrap();
+        let keys: Vec<(&[u8], &[Interval])> = history.previous.iter().map(|(k, i)| (k.as_slice(), i.as_slice())).collect();
+        assert_eq!(keys, [
+            (&b"BTC"[..], &[closed(2, 100, 200)][..]),
+            (b"ETH", &[live(1, 100)]),
+            (b"UNI", &[live(10, 150)]),
+        ]);
+        assert_eq!(history.since, 200);
+        let _ = fs::remove_dir_all(&left);
+        let _ = fs::remove_dir_all(&right);
+    }
+}
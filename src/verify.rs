@@ -0,0 +1,108 @@
+use std::path::Path;
+use fst::Map;
+use crate::bytes::{Bytes, LoadMode};
+use crate::dense;
+use crate::manifest::{FileEntry, Manifest};
+use crate::reverse::{self, ReverseIndex};
+use crate::validity::{self, Validity};
+use crate::topic_map::MAP_FILE;
+
+/// Checks every file listed in the manifest against its recorded size and
+/// BLAKE3, validates FST structure, and cross-checks entry counts.
+pub fn verify_snapshot(dir: &Path) -> anyhow::Result<Manifest> {
+    let manifest = Manifest::read(dir)?;
+    if manifest.files.is_empty() {
+        anyhow::bail!(
+            "{}: manifest records no file checksums (built before v2?), rebuild the snapshot",
+            dir.display()
+        );
+    }
+    if manifest.file(MAP_FILE).is_none() {
+        anyhow::bail!("{}: manifest does not list {MAP_FILE}", dir.display());
+    }
+
+    for entry in &manifest.files {
+        let path = dir.join(&entry.name);
+        let bytes = Bytes::load(&path, LoadMode::default())?;
+        verify_file(entry, bytes.as_ref())?;
+
+        if entry.name.ends_with(".fst") {
+            let map = verify_fst(&entry.name, bytes)?;
+            let expected = if entry.name == dense::DENSE_FST_FILE {
+                manifest.dense.as_ref().map(|d| d.count)
+            } else if entry.name == validity::VALIDITY_FST_FILE {
+                manifest.validity.as_ref().map(|v| v.keys)
+            } else {
+                let variants = manifest.variants.as_ref().map_or(0, |v| v.count);
+                Some(manifest.sources.values().map(|s| s.count).sum::<u64>() + variants)
+            };
+            if let Some(expected) = expected {
+                if map.len() as u64 != expected {
+                    anyhow::bail!(
+                        "{}: {} entries, manifest expects {expected}",
+                        entry.name, map.len()
+                    );
+                }
+            }
+        }
+    }
+
+    if let Some(d) = &manifest.dense {
+        let ids = dense::DenseIds::read(&dir.join(dense::DENSE_IDS_FILE))?;
+        if ids.len() as u64 != d.count {
+            anyhow::bail!(
+                "{}: {} IDs, manifest expects {}",
+                dense::DENSE_IDS_FILE, ids.len(), d.count
+            );
+        }
+    }
+
+    if let Some(v) = &manifest.validity {
+        let table = Validity::open(dir, LoadMode::default())?;
+        if table.len() as u64 != v.intervals {
+            anyhow::bail!(
+                "{}: {} intervals, manifest expects {}",
+                validity::VALIDITY_FILE, table.len(), v.intervals
+            );
+        }
+    }
+
+    if manifest.file(reverse::REVERSE_FILE).is_some() {
+        let rev = ReverseIndex::open(&dir.join(reverse::REVERSE_FILE), LoadMode::default())?;
+        let expected: u64 = manifest.sources.values().map(|s| s.count).sum::<u64>()
+            + manifest.aliases.as_ref().map_or(0, |a| a.count);
+        if rev.len() as u64 != expected {
+            anyhow::bail!(
+                "{}: {} entries, manifest expects {expected}",
+                reverse::REVERSE_FILE, rev.len()
+            );
+        }
+    }
+
+    Ok(manifest)
+}
+
+pub fn verify_file(entry: &FileEntry, bytes: &[u8]) -> anyhow::Result<()> {
+    if bytes.len() as u64 != entry.size {
+        anyhow::bail!(
+            "{}: size {} does not match manifest size {} (truncated?)",
+            entry.name, bytes.len(), entry.size
+        );
+    }
+    let digest = blake3::hash(bytes).to_hex();
+    if digest.as_str() != entry.blake3 {
+        anyhow::bail!(
+            "{}: blake3 {} does not match manifest {}",
+            entry.name, digest, entry.blake3
+        );
+    }
+    Ok(())
+}
+
+// `Map::new` only checks the header and footer; `verify` checks the FST's
+// own checksum over the whole body.
+pub fn verify_fst<D: AsRef<[u8]>>(name: &str, bytes: D) -> anyhow::Result<Map<D>> {
+    let map = Map::new(bytes).map_err(|e| anyhow::anyhow!("{name}: {e}"))?;
+    map.as_fst().verify().map_err(|e| anyhow::anyhow!("{name}: {e}"))?;
+    Ok(map)
+}
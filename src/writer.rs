@@ -0,0 +1,241 @@
+use std::{fs, io::BufWriter, path::{Path, PathBuf}};
+use fst::{Map, MapBuilder, Streamer};
+use crate::bytes::{Bytes, LoadMode};
+use crate::manifest::{AliasEntry, DenseEntry, FileEntry, Manifest, VariantEntry};
+use crate::normalize::Variant;
+use crate::report::Phase;
+use crate::topic_map::MAP_FILE;
+use crate::validity::{self, History};
+use crate::{dense, reverse, snapshot, utils, Pair};
+
+pub struct Written {
+    /// Snapshot directory name under the root, e.g. `000007`.
+    pub name: String,
+    pub dir: PathBuf,
+    /// Entries in the written map.
+    pub len: u64,
+    pub phases: Vec<Phase>,
+}
+
+/// `stage` followed by `publish`. Pruning old snapshots is left to the caller.
+pub fn write_snapshot<I>(
+    root: &Path,
+    manifest: &mut Manifest,
+    pairs: I,
+    variants: Vec<Variant>,
+    history: Option<History>,
+    dense_ids: bool,
+) -> anyhow::Result<Written>
+where
+    I: IntoIterator<Item = anyhow::Result<Pair>>,
+{
+    let mut written = stage(root, manifest, pairs, variants, history, dense_ids)?;
+    written.publish(root)?;
+    Ok(written)
+}
+
+/// Streams `pairs` (sorted by key) into a fresh snapshot under `root`, derives
+/// the other tables from the written FST, records every file in the manifest
+/// and syncs, without switching `current` to it yet. A repeated key is an
+/// alias: the map keeps its first ID and the reverse index resolves both.
+/// Source counts are recomputed from what was written. `variants` are
+/// (spelling, key) pairs added to the map as extra keys for the key's ID.
+/// With a `history`, key validity intervals are carried forward from the
+/// previous snapshot. On error the snapshot directory is removed.
+pub fn stage<I>(
+    root: &Path,
+    manifest: &mut Manifest,
+    pairs: I,
+    variants: Vec<Variant>,
+    history: Option<History>,
+    dense_ids: bool,
+) -> anyhow::Result<Written>
+where
+    I: IntoIterator<Item = anyhow::Result<Pair>>,
+{
+    let (name, dir) = snapshot::create_next(root)?;
+    let mut phases = vec![];
+    match write_files(&dir, manifest, pairs, variants, history, dense_ids, &mut phases) {
+        Ok(len) => Ok(Written { name, dir, len, phases }),
+        Err(e) => {
+            let _ = fs::remove_dir_all(&dir);
+            Err(e)
+        }
+    }
+}
+
+fn write_files<I>(
+    dir: &Path,
+    manifest: &mut Manifest,
+    pairs: I,
+    variants: Vec<Variant>,
+    history: Option<History>,
+    dense_ids: bool,
+    phases: &mut Vec<Phase>,
+) -> anyhow::Result<u64>
+where
+    I: IntoIterator<Item = anyhow::Result<Pair>>,
+{
+    // Pulling `pairs` drives the k-way merge of a build, so it's timed with the FST
+    let (len, aliases) = Phase::time(phases, "merge_fst", || write_fst(&dir.join(MAP_FILE), pairs))?;
+    manifest.files.push(FileEntry::from_file(dir, MAP_FILE)?);
+    let mut map = Map::new(Bytes::load(&dir.join(MAP_FILE), LoadMode::Mmap)?)?;
+    recount(manifest, &map);
+    manifest.aliases = (!aliases.is_empty()).then_some(AliasEntry { count: aliases.len() as u64 });
+    Phase::time(phases, "reverse", || reverse::write(&dir.join(reverse::REVERSE_FILE), &map, &aliases))?;
+    manifest.files.push(FileEntry::from_file(dir, reverse::REVERSE_FILE)?);
+
+    // Dense IDs are per topic, so they come from the keys alone
+    if dense_ids {
+        let count = Phase::time(phases, "dense", || dense::write(dir, &map))?;
+        manifest.dense = Some(DenseEntry { count });
+        manifest.files.push(FileEntry::from_file(dir, dense::DENSE_FST_FILE)?);
+        manifest.files.push(FileEntry::from_file(dir, dense::DENSE_IDS_FILE)?);
+    }
+
+    if !variants.is_empty() {
+        let count = Phase::time(phases, "variants", || add_variants(dir, &map, variants))?;
+        manifest.variants = Some(VariantEntry { count });
+        map = Map::new(Bytes::load(&dir.join(MAP_FILE), LoadMode::Mmap)?)?;
+        let entry = manifest.files.iter_mut().find(|f| f.name == MAP_FILE).unwrap();
+        *entry = FileEntry::from_file(dir, MAP_FILE)?;
+    }
+
+    if let Some(history) = history {
+        let now = manifest.built_at.unwrap_or(0);
+        let entry = Phase::time(phases, "validity", || validity::write(dir, &map, now, history))?;
+        manifest.validity = Some(entry);
+        manifest.files.push(FileEntry::from_file(dir, validity::VALIDITY_FST_FILE)?);
+        manifest.files.push(FileEntry::from_file(dir, validity::VALIDITY_FILE)?);
+    }
+
+    Phase::time(phases, "manifest", || -> anyhow::Result<()> {
+        manifest.write(dir)?;
+        snapshot::sync_dir(dir)
+    })?;
+    Ok(len)
+}
+
+// Dedup can drop keys after IDs were assigned, so counts come from the map
+fn recount<D: AsRef<[u8]>>(manifest: &mut Manifest, map: &Map<D>) {
+    let mut ranges: Vec<(u64, u64, &mut u64)> = manifest.sources.values_mut()
+        .map(|s| {
+            s.count = 0;
+            (s.id_start, s.id_end, &mut s.count)
+        })
+        .collect();
+    let mut stream = map.stream();
+    while let Some((_, id)) = stream.next() {
+        if let Some((_, _, count)) = ranges.iter_mut().find(|(start, end, _)| (*start..*end).contains(&id)) {
+            **count += 1;
+        }
+    }
+}
+
+// Rewrites the map with variant spellings as extra keys, so plain lookups
+// resolve them. Variants resolve through their key, so they follow it
+// through dedup; a spelling that is itself a key, or that two keys share,
+// goes to the key (or lowest ID). Returns the number added.
+fn add_variants<D: AsRef<[u8]>>(dir: &Path, map: &Map<D>, variants: Vec<Variant>) -> anyhow::Result<u64> {
+    let mut extra: Vec<Pair> = variants.into_iter()
+        .filter(|(spelling, _)| map.get(spelling).is_none())
+        .filter_map(|(spelling, key)| Some((spelling, map.get(&key)?)))
+        .collect();
+    extra.sort_unstable();
+    extra.dedup_by(|a, b| a.0 == b.0);
+    let count = extra.len() as u64;
+
+    let mut extra = extra.into_iter().peekable();
+    let mut stream = map.stream();
+    let mut next_key = stream.next().map(|(k, id)| (k.to_vec(), id));
+    let merged = std::iter::from_fn(|| {
+        let take_extra = match (extra.peek(), &next_key) {
+            (Some((spelling, _)), Some((key, _))) => spelling < key,
+            (extra, _) => extra.is_some(),
+        };
+        if take_extra {
+            return extra.next().map(Ok);
+        }
+        let pair = next_key.take()?;
+        next_key = stream.next().map(|(k, id)| (k.to_vec(), id));
+        Some(Ok(pair))
+    });
+    let tmp = dir.join(format!("{MAP_FILE}.tmp"));
+    write_fst(&tmp, merged)?;
+    fs::rename(&tmp, dir.join(MAP_FILE))?;
+    Ok(count)
+}
+
+impl Written {
+    /// Switches `current` to this snapshot.
+    pub fn publish(&mut self, root: &Path) -> anyhow::Result<()> {
+        Phase::time(&mut self.phases, "publish", || snapshot::publish(root, &self.name))
+    }
+
+    /// Deletes a staged snapshot that won't be published.
+    pub fn discard(self) -> anyhow::Result<()> {
+        fs::remove_dir_all(&self.dir)?;
+        Ok(())
+    }
+}
+
+/// Writes key-sorted pairs as an FST map. Returns the entry count and the
+/// (key, ID) of every repeated key, which is not in the map.
+pub fn write_fst<I>(path: &Path, pairs: I) -> anyhow::Result<(u64, Vec<Pair>)>
+where
+    I: IntoIterator<Item = anyhow::Result<Pair>>,
+{
+    let file = BufWriter::new(fs::File::create(path)?);
+    let mut builder = MapBuilder::new(file)?;
+    let mut len = 0;
+    let mut last: Option<Vec<u8>> = None;
+    let mut aliases = vec![];
+    for pair in pairs {
+        let (k, v) = pair?;
+        if last.as_ref() == Some(&k) {
+            aliases.push((k, v));
+            continue;
+        }
+        builder.insert(&k, v)
+            .map_err(|e| anyhow::anyhow!("{}: key {}: {e}", path.display(), utils::display_key(&k)))?;
+        last = Some(k);
+        len += 1;
+    }
+    builder.finish()?;
+    Ok((len, aliases))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testutil, verify, TopicMap};
+
+    #[test]
+    fn variants_are_extra_keys_for_their_key() {
+        let root = testutil::root("writer-variants");
+        let mut manifest = testutil::manifest(100, &[("binance", 0, 10)]);
+        let pairs = [("BTCUSDT", 0), ("ETHUSDT", 1), ("ethusdt", 2)]
+            .map(|(k, id)| Ok((k.as_bytes().to_vec(), id)));
+        let variant = |spelling: &str, key: &str| (spelling.as_bytes().to_vec(), key.as_bytes().to_vec());
+        let variants = vec![
+            variant("eth-usdt", "ETHUSDT"),
+            variant("eth-usdt", "BTCUSDT"),     // shared: the lowest ID wins
+            variant("ethusdt", "ETHUSDT"),      // a key of its own
+            variant("sol-usdt", "SOLUSDT"),     // key not in the map
+            variant("btc/usdt", "BTCUSDT"),
+        ];
+        let written = write_snapshot(&root, &mut manifest, pairs, variants, None, true).unwrap();
+        assert_eq!(manifest.variants, Some(VariantEntry { count: 2 }));
+        assert_eq!(manifest.sources["binance"].count, 3);
+        assert_eq!(manifest.dense.as_ref().unwrap().count, 3);
+        verify::verify_snapshot(&written.dir).unwrap();
+
+        let map = TopicMap::open(&written.dir).unwrap();
+        assert_eq!(map.get("eth-usdt"), Some(0));
+        assert_eq!(map.get("btc/usdt"), Some(0));
+        assert_eq!(map.get("ethusdt"), Some(2));
+        assert_eq!(map.get("sol-usdt"), None);
+        assert_eq!(map.key_of(0).unwrap(), b"BTCUSDT");
+        let _ = fs::remove_dir_all(&root);
+    }
+}